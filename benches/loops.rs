@@ -0,0 +1,134 @@
+//! Times loop-heavy programs, run with `cargo bench`.
+//!
+//! Each program is run through `Program::run`, `run_ir` and `run_jit`. Before
+//! those, the `scan` and `tbl` rows are a micro-benchmark of bracket lookup
+//! alone: `baseline` is a bare interpreter that either scans for the matching
+//! bracket on every jump, as `Program` used to, or looks it up in a table.
+//! `Program` also counts steps and checks limits and the tape's edges, so its
+//! times aren't comparable with those two.
+use std::io::{Read, Sink, Write};
+use std::time::Instant;
+
+use brainfuck::{parse, BfError, Operation, Program};
+
+/// Nested counting loops with long bodies, so every jump has a lot to skip over.
+fn nested_loops() -> String {
+    let count = "+".repeat(16);
+    let body = ">+<".repeat(50);
+    format!("{c}[>{c}[>{c}[>{c}[{b}-]<-]<-]<-]", c = count, b = body)
+}
+
+/// The classic multiplication idiom on a cell count that keeps the loop busy.
+fn mul_loops() -> String {
+    "++++++++[>++++++++[>++++++++[>+>++<<-]<-]<-]>>>[-]>[-]<<<<".repeat(200)
+}
+
+/// Text for rot13 to work through.
+fn rot13_input() -> Vec<u8> {
+    b"The quick brown fox jumps over the lazy dog.\n".repeat(500)
+}
+
+fn bench(name: &str, src: &str, input: &[u8]) {
+    let ops = parse(src.as_bytes()).expect("benchmark source should parse");
+
+    let table: Vec<_> = (0..ops.len()).map(|ip| partner(&ops, ip)).collect();
+    time(name, "scan", || {
+        baseline(&ops, input, std::io::sink(), |ip| partner(&ops, ip))
+    });
+    time(name, "tbl", || {
+        baseline(&ops, input, std::io::sink(), |ip| table[ip])
+    });
+    time(name, "run", || run(&ops, input, Program::run));
+    time(name, "ir", || run(&ops, input, Program::run_ir));
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    time(name, "jit", || run(&ops, input, Program::run_jit));
+}
+
+/// Run `f` once and print how long it took.
+fn time(name: &str, mode: &str, f: impl FnOnce() -> Result<(), BfError>) {
+    let start = Instant::now();
+    f().expect("benchmark program should run");
+    println!("{:<14} {:<4} {:>10.2?}", name, mode, start.elapsed());
+}
+
+fn run<'a, R>(ops: &[Operation], input: &'a [u8], run: R) -> Result<(), BfError>
+where
+    R: Fn(&mut Program<&'a [u8], Sink>) -> Result<(), BfError>,
+{
+    run(&mut Program::with_io(ops.to_vec(), input, std::io::sink()))
+}
+
+/// A byte tape interpreter that finds the bracket matching the one at `ip` with `jump`.
+fn baseline(
+    ops: &[Operation],
+    mut input: impl Read,
+    mut output: impl Write,
+    jump: impl Fn(usize) -> usize,
+) -> Result<(), BfError> {
+    let mut memory = vec![0u8; 512];
+    let (mut ip, mut ptr) = (0, 0);
+    while ip < ops.len() {
+        match ops[ip] {
+            Operation::MoveRight => {
+                ptr += 1;
+                if ptr == memory.len() {
+                    memory.resize(ptr * 2, 0);
+                }
+            }
+            Operation::MoveLeft => {
+                ptr = ptr
+                    .checked_sub(1)
+                    .ok_or(BfError::PointerOutOfBounds(Some(ip)))?
+            }
+            Operation::Increment => memory[ptr] = memory[ptr].wrapping_add(1),
+            Operation::Decrement => memory[ptr] = memory[ptr].wrapping_sub(1),
+            Operation::Output => output.write_all(&memory[ptr..=ptr])?,
+            Operation::Input => {
+                let mut byte = [0];
+                if input.read(&mut byte)? == 1 {
+                    memory[ptr] = byte[0];
+                }
+            }
+            Operation::JumpForward if memory[ptr] == 0 => ip = jump(ip),
+            Operation::JumpBack if memory[ptr] != 0 => ip = jump(ip),
+            _ => {}
+        }
+        ip += 1;
+    }
+    Ok(())
+}
+
+/// Index of the bracket matching the one at `ip`, scanning towards it.
+///
+/// Anything other than a bracket is its own partner.
+fn partner(ops: &[Operation], mut ip: usize) -> usize {
+    let step = match ops[ip] {
+        Operation::JumpForward => 1,
+        Operation::JumpBack => -1,
+        _ => return ip,
+    };
+    let mut depth = 0;
+    loop {
+        match ops[ip] {
+            Operation::JumpForward => depth += step,
+            Operation::JumpBack => depth -= step,
+            _ => {}
+        }
+        if depth == 0 {
+            return ip;
+        }
+        ip = ip.wrapping_add_signed(step);
+    }
+}
+
+fn main() {
+    println!("{:<14} {:<4} {:>10}", "program", "mode", "time");
+    bench("nested_loops", &nested_loops(), b"");
+    bench("mul_loops", &mul_loops(), b"");
+    bench("squares", include_str!("../tests/programs/squares.bf"), b"");
+    bench(
+        "rot13",
+        include_str!("../tests/programs/rot13.bf"),
+        &rot13_input(),
+    );
+}
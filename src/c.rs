@@ -0,0 +1,345 @@
+//! Transpiles `Ir` to a self-contained C program.
+//!
+//! The generated program behaves like `Program::run`: the tape starts at
+//! `tape_size` cells and doubles when the pointer runs off the right end,
+//! moving left of the first cell is an error, `.` writes according to
+//! `output_mode` and `,` reads according to `input_mode` and `eof`. `#` lists
+//! the cells around the pointer on stderr in the same table as `--debug-hash`.
+//! Errors exit with status 3, like the command line does for a program that
+//! fails while running.
+use std::fmt::Write;
+
+use crate::ir::Ir;
+use crate::{EofPolicy, InputMode, OutputMode};
+
+/// Settings for the generated C.
+pub struct COptions {
+    /// Number of cells allocated up front.
+    pub tape_size: usize,
+    /// C type of a cell, must be an unsigned integer type.
+    pub cell_type: String,
+    /// What `,` does once input has run out.
+    pub eof: EofPolicy,
+    /// How `,` reads input.
+    pub input_mode: InputMode,
+    /// How `.` writes output.
+    pub output_mode: OutputMode,
+}
+
+impl Default for COptions {
+    fn default() -> Self {
+        Self {
+            tape_size: 512,
+            cell_type: "uint8_t".to_string(),
+            eof: EofPolicy::default(),
+            input_mode: InputMode::default(),
+            output_mode: OutputMode::default(),
+        }
+    }
+}
+
+const INCLUDES: &str = "#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+";
+
+const PRELUDE: &str = r#"
+static cell *tape;
+static size_t len = TAPE_SIZE;
+static size_t ptr = 0;
+
+static void fail(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s\n", message);
+    exit(3);
+}
+
+/* Make sure the cell at `index` exists, doubling the tape if needed. */
+static size_t ensure(ptrdiff_t index) {
+    if (index < 0) {
+        fail("pointer moved left of the first cell");
+    }
+    if ((size_t)index >= len) {
+        size_t grown = len * 2 > (size_t)index + 1 ? len * 2 : (size_t)index + 1;
+        tape = realloc(tape, grown * sizeof(cell));
+        if (tape == NULL) {
+            fail("out of memory");
+        }
+        memset(tape + len, 0, (grown - len) * sizeof(cell));
+        len = grown;
+    }
+    return (size_t)index;
+}
+
+static void move(ptrdiff_t n) {
+    ptr = ensure((ptrdiff_t)ptr + n);
+}
+
+static cell *at(ptrdiff_t offset) {
+    return &tape[ensure((ptrdiff_t)ptr + offset)];
+}
+
+"#;
+
+/// `output` for `OutputMode::Raw`.
+const OUTPUT_RAW: &str = r#"static void output(void) {
+    putchar((unsigned char)tape[ptr]);
+}
+"#;
+
+/// `output` for `OutputMode::Decimal`.
+const OUTPUT_DECIMAL: &str = r#"static void output(void) {
+    printf("%llu\n", (unsigned long long)tape[ptr]);
+}
+"#;
+
+/// `output` for `OutputMode::Hex`.
+const OUTPUT_HEX: &str = r#"static void output(void) {
+    printf("%02llx\n", (unsigned long long)tape[ptr]);
+}
+"#;
+
+/// `output` for `OutputMode::Latin1`.
+const OUTPUT_LATIN1: &str = r#"static void output(void) {
+    unsigned char c = (unsigned char)tape[ptr];
+    if (c < 0x80) {
+        putchar(c);
+    } else {
+        putchar(0xC0 | (c >> 6));
+        putchar(0x80 | (c & 0x3F));
+    }
+}
+"#;
+
+/// `input` for `InputMode::Byte`.
+const INPUT_BYTE: &str = r#"/* Read a single byte. */
+static void input(void) {
+    int c;
+
+    fflush(stdout);
+    if ((c = getchar()) == EOF) {
+        ON_EOF
+        return;
+    }
+    tape[ptr] = (cell)(unsigned char)c;
+}
+"#;
+
+/// `input` for `InputMode::DecimalLine`.
+const INPUT_DECIMAL: &str = r#"/* Read a line holding a decimal cell value. */
+static void input(void) {
+    char line[256];
+    size_t n = 0;
+    size_t read = 0;
+    int c;
+
+    fflush(stdout);
+    while ((c = getchar()) != EOF && c != '\n') {
+        read++;
+        if (n + 1 < sizeof(line)) {
+            line[n++] = (char)c;
+        }
+    }
+    if (c == EOF && read == 0) {
+        ON_EOF
+        return;
+    }
+    line[n] = '\0';
+
+    char *start = line;
+    char *end = line + n;
+    while (*start == ' ' || *start == '\t' || *start == '\r') {
+        start++;
+    }
+    while (end > start && (end[-1] == ' ' || end[-1] == '\t' || end[-1] == '\r')) {
+        *--end = '\0';
+    }
+    if (*start == '+') {
+        start++;
+    }
+
+    unsigned long long value = 0;
+    if (*start == '\0') {
+        fail("invalid input");
+    }
+    for (char *d = start; *d != '\0'; d++) {
+        if (*d < '0' || *d > '9') {
+            fail("invalid input");
+        }
+        value = value * 10 + (unsigned long long)(*d - '0');
+        if (value > (cell)-1) {
+            fail("invalid input");
+        }
+    }
+    tape[ptr] = (cell)value;
+}
+"#;
+
+/// `dump` for programs that use `#`, laid out like `Tape::dump`.
+const DUMP: &str = r##"
+/* Write a table of the cells around the pointer to stderr. */
+static void dump(void) {
+    static const char *labels[] = {"cell", "hex", "dec"};
+    size_t first = ptr < 8 ? 0 : ptr - 8;
+    size_t last = ptr + 8 < len ? ptr + 8 : len - 1;
+    char columns[17][3][24];
+    int width = 0;
+
+    for (size_t i = first; i <= last; i++) {
+        unsigned long long value = (unsigned long long)tape[i];
+        char (*column)[24] = columns[i - first];
+        snprintf(column[0], sizeof column[0], "%zu", i);
+        snprintf(column[1], sizeof column[1], "%02llx", value);
+        snprintf(column[2], sizeof column[2], "%llu", value);
+        for (int row = 0; row < 3; row++) {
+            int n = (int)strlen(column[row]);
+            width = n > width ? n : width;
+        }
+    }
+    fprintf(stderr, "# pointer at cell %zu\n", ptr);
+    for (int row = 0; row < 3; row++) {
+        fprintf(stderr, "%-4s", labels[row]);
+        for (size_t i = first; i <= last; i++) {
+            fprintf(stderr, " %*s", width, columns[i - first][row]);
+        }
+        fputc('\n', stderr);
+    }
+    fprintf(stderr, "    %*s\n", (int)(ptr - first + 1) * (width + 1), "^");
+}
+"##;
+
+const MAIN: &str = r#"
+int main(void) {
+    tape = calloc(len, sizeof(cell));
+    if (tape == NULL) {
+        fail("out of memory");
+    }
+"#;
+
+/// Translate `ir` into the source of a C program.
+pub fn compile(ir: &[(Ir, usize)], options: &COptions) -> String {
+    let mut out = INCLUDES.to_string();
+    writeln!(out, "typedef {} cell;", options.cell_type).unwrap();
+    writeln!(out, "#define TAPE_SIZE {}", options.tape_size.max(1)).unwrap();
+    let on_eof = match options.eof {
+        EofPolicy::Unchanged => "",
+        EofPolicy::Zero => "tape[ptr] = 0;",
+        EofPolicy::MinusOne => "tape[ptr] = (cell)-1;",
+    };
+    writeln!(out, "#define ON_EOF {}", on_eof).unwrap();
+    out.push_str(PRELUDE);
+    out.push_str(match options.output_mode {
+        OutputMode::Raw => OUTPUT_RAW,
+        OutputMode::Decimal => OUTPUT_DECIMAL,
+        OutputMode::Hex => OUTPUT_HEX,
+        OutputMode::Latin1 => OUTPUT_LATIN1,
+    });
+    out.push('\n');
+    out.push_str(match options.input_mode {
+        InputMode::Byte => INPUT_BYTE,
+        InputMode::DecimalLine => INPUT_DECIMAL,
+    });
+    if dumps(ir) {
+        out.push_str(DUMP);
+    }
+    out.push_str(MAIN);
+    block(&mut out, ir, 1);
+    out.push_str("    fflush(stdout);\n    return 0;\n}\n");
+    out
+}
+
+fn block(out: &mut String, ir: &[(Ir, usize)], depth: usize) {
+    let indent = "    ".repeat(depth);
+    for (instr, _) in ir {
+        out.push_str(&indent);
+        match instr {
+            Ir::Add(delta) => writeln!(out, "tape[ptr] += {};", delta),
+            Ir::Move(n) => writeln!(out, "move({});", n),
+            Ir::AddAt { offset, delta } => writeln!(out, "*at({}) += {};", offset, delta),
+            Ir::Check { low, high } => writeln!(out, "(void)at({}); (void)at({});", low, high),
+            Ir::Output => writeln!(out, "output();"),
+            Ir::Input => writeln!(out, "input();"),
+            Ir::DebugDump => writeln!(out, "dump();"),
+            Ir::Loop(body) => {
+                out.push_str("while (tape[ptr]) {\n");
+                block(out, body, depth + 1);
+                writeln!(out, "{}}}", indent)
+            }
+            Ir::SetZero => writeln!(out, "tape[ptr] = 0;"),
+            // `at` may reallocate the tape, so read the counter first. Multiplied
+            // unsigned, as narrow cells would otherwise overflow a signed int
+            Ir::MulAdd { offset, factor } => writeln!(
+                out,
+                "if (tape[ptr]) {{ cell n = (cell)((unsigned long long)tape[ptr] * (unsigned long long){}); *at({}) += n; }}",
+                factor, offset
+            ),
+            Ir::ScanRight => writeln!(out, "while (tape[ptr]) move(1);"),
+            Ir::ScanLeft => writeln!(out, "while (tape[ptr]) move(-1);"),
+        }
+        .unwrap();
+    }
+}
+
+/// Whether `ir` uses `#` anywhere.
+fn dumps(ir: &[(Ir, usize)]) -> bool {
+    ir.iter().any(|(instr, _)| match instr {
+        Ir::DebugDump => true,
+        Ir::Loop(body) => dumps(body),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ir, parse};
+
+    fn compile_src(src: &str) -> String {
+        let ir = ir::optimize(ir::lower(&parse(src.as_bytes()).unwrap()).unwrap());
+        compile(&ir, &COptions::default())
+    }
+
+    #[test]
+    fn c_header() {
+        let c = compile_src("");
+        assert!(c.contains("\ntypedef uint8_t cell;\n#define TAPE_SIZE 512\n"));
+        assert!(c.ends_with("    return 0;\n}\n"));
+    }
+
+    #[test]
+    fn c_body() {
+        let c = compile_src("+[->++<]>[.>,]");
+        assert!(c.contains(
+            "    tape[ptr] += 1;\n    if (tape[ptr]) { cell n = (cell)((unsigned long long)tape[ptr] * (unsigned long long)2); *at(1) += n; }\n    tape[ptr] = 0;\n    move(1);\n    while (tape[ptr]) {\n        output();\n        move(1);\n        input();\n    }\n"
+        ));
+    }
+
+    #[test]
+    fn c_debug_dump() {
+        assert!(!compile_src("+.").contains("static void dump(void)"));
+        let c = compile(
+            &[(Ir::Loop(vec![(Ir::DebugDump, 1)]), 0)],
+            &COptions::default(),
+        );
+        assert!(c.contains("static void dump(void)"));
+        assert!(c.contains("    while (tape[ptr]) {\n        dump();\n    }\n"));
+    }
+
+    #[test]
+    fn c_options() {
+        let options = COptions {
+            tape_size: 30000,
+            cell_type: "uint16_t".to_string(),
+            eof: EofPolicy::MinusOne,
+            input_mode: InputMode::DecimalLine,
+            output_mode: OutputMode::Hex,
+        };
+        let c = compile(&[], &options);
+        assert!(c.contains("/* Read a line holding a decimal cell value. */"));
+        assert!(c.contains(r#"printf("%02llx\n", (unsigned long long)tape[ptr]);"#));
+        assert!(c.contains("\ntypedef uint16_t cell;\n#define TAPE_SIZE 30000\n#define ON_EOF tape[ptr] = (cell)-1;\n"));
+    }
+}
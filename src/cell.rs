@@ -0,0 +1,82 @@
+use std::fmt::{Debug, Display, LowerHex};
+use std::str::FromStr;
+
+/// The value stored in a memory cell.
+///
+/// All arithmetic wraps around, so a program sees the cell as a fixed-width integer.
+pub trait Cell: Copy + Default + PartialEq + Debug + Display + LowerHex + FromStr {
+    /// Add `delta`, wrapping.
+    fn wrapping_add_i32(self, delta: i32) -> Self;
+
+    /// Add `value * factor`, wrapping.
+    fn wrapping_mul_add(self, value: Self, factor: i32) -> Self;
+
+    /// Whether this is the zero cell, which ends a loop.
+    fn is_zero(self) -> bool {
+        self == Self::default()
+    }
+
+    /// Widen a byte read from input.
+    fn from_byte(byte: u8) -> Self;
+
+    /// The low byte, for writing to output.
+    fn to_byte(self) -> u8;
+}
+
+macro_rules! impl_cell {
+    ($($t:ty),*) => {$(
+        impl Cell for $t {
+            fn wrapping_add_i32(self, delta: i32) -> Self {
+                // Truncating is the same as wrapping for two's complement
+                self.wrapping_add(delta as $t)
+            }
+
+            fn wrapping_mul_add(self, value: Self, factor: i32) -> Self {
+                self.wrapping_add(value.wrapping_mul(factor as $t))
+            }
+
+            fn from_byte(byte: u8) -> Self {
+                byte as $t
+            }
+
+            fn to_byte(self) -> u8 {
+                self as u8
+            }
+        }
+    )*};
+}
+
+impl_cell!(u8, u16, u32, i8, i16, i32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_wrapping() {
+        assert_eq!(255u8.wrapping_add_i32(1), 0);
+        assert_eq!(0u8.wrapping_add_i32(-1), 255);
+        assert_eq!(255u16.wrapping_add_i32(1), 256);
+        assert_eq!(0u16.wrapping_add_i32(-1), u16::MAX);
+        assert_eq!(0u32.wrapping_add_i32(-1), u32::MAX);
+        assert_eq!(127i8.wrapping_add_i32(1), -128);
+        assert_eq!(0i16.wrapping_add_i32(-1), -1);
+        assert_eq!(0u8.wrapping_add_i32(300), 44);
+    }
+
+    #[test]
+    fn cell_mul_add() {
+        assert_eq!(1u8.wrapping_mul_add(200, 2), 145);
+        assert_eq!(1u16.wrapping_mul_add(200, 2), 401);
+        assert_eq!(0i8.wrapping_mul_add(3, -2), -6);
+    }
+
+    #[test]
+    fn cell_bytes() {
+        assert!(0u32.is_zero());
+        assert!(!(-1i8).is_zero());
+        assert_eq!(u16::from_byte(200), 200);
+        assert_eq!(0x1234u16.to_byte(), 0x34);
+        assert_eq!((-1i32).to_byte(), 255);
+    }
+}
@@ -0,0 +1,314 @@
+//! An interactive debugger that steps a `Program` under the control of text commands.
+//!
+//! Commands are read a line at a time, an empty line repeats the previous one:
+//!
+//! - `step [n]`, `s`: execute one (or `n`) instructions
+//! - `next`, `n`: like `step`, but run a whole loop when on a `[`
+//! - `continue`, `c`: run until a breakpoint or the end of the program
+//! - `break <index>` or `break <line>:<column>`, `b`: stop before an instruction
+//! - `delete <index>`, `d`: remove a breakpoint
+//! - `breakpoints`: list the breakpoints
+//! - `where`, `w`: show the current instruction in its source
+//! - `memory [radius]`, `m`: show the cells around the pointer in hex and decimal
+//! - `help`, `h`: list the commands
+//! - `quit`, `q`: stop debugging
+use std::collections::BTreeSet;
+use std::io::prelude::*;
+
+use crate::{BfError, Cell, Operation, Program, SourceMap};
+
+const HELP: &str = "commands:
+    step [n]                  execute one or n instructions (s)
+    next                      like step, but run a whole loop when on a `[` (n)
+    continue                  run until a breakpoint or the end (c)
+    break <index|line:column> stop before an instruction (b)
+    delete <index>            remove a breakpoint (d)
+    breakpoints               list the breakpoints
+    where                     show the current instruction (w)
+    memory [radius]           show the cells around the pointer (m)
+    help                      show this message (h)
+    quit                      stop debugging (q)";
+
+/// Cells shown either side of the pointer by `memory` without a radius.
+const RADIUS: usize = 8;
+
+/// Steps a program according to commands, reporting on where it is.
+pub struct Debugger<'p, R, W, C: Cell> {
+    program: &'p mut Program<R, W, C>,
+    /// Lines of the source, without their line endings
+    lines: Vec<String>,
+    /// Where every instruction is in the source
+    map: &'p SourceMap,
+    /// Instruction indices to stop before
+    breakpoints: BTreeSet<usize>,
+    /// Set once the program has failed, it can't go any further
+    stopped: bool,
+    /// The last command, repeated by an empty line
+    last: String,
+}
+
+impl<'p, R: Read, W: Write, C: Cell> Debugger<'p, R, W, C> {
+    /// Debug `program`, which was parsed from `source` into `map`.
+    pub fn new(program: &'p mut Program<R, W, C>, source: &[u8], map: &'p SourceMap) -> Self {
+        Self {
+            program,
+            lines: String::from_utf8_lossy(source)
+                .lines()
+                .map(str::to_string)
+                .collect(),
+            map,
+            breakpoints: BTreeSet::new(),
+            stopped: false,
+            last: String::new(),
+        }
+    }
+
+    /// Read and run commands from `commands` until it ends or says to quit,
+    /// writing a prompt and the results to `out`.
+    pub fn run<I: BufRead, O: Write>(&mut self, commands: I, mut out: O) -> Result<(), BfError> {
+        writeln!(
+            out,
+            "{} instructions, type `help` for a list of commands",
+            self.map.len()
+        )?;
+        self.show(&mut out)?;
+        let mut lines = commands.lines();
+        loop {
+            write!(out, "(bf) ")?;
+            out.flush()?;
+            let Some(line) = lines.next() else {
+                writeln!(out)?;
+                return Ok(());
+            };
+            if !self.command(&line?, &mut out)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Run a single command, returning `false` if it was `quit`.
+    pub fn command<O: Write>(&mut self, line: &str, out: &mut O) -> Result<bool, BfError> {
+        let line = match line.trim() {
+            "" => self.last.clone(),
+            line => line.to_string(),
+        };
+        self.last = line.clone();
+
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let arg = words.next();
+        match command {
+            "step" | "s" => match arg.map_or(Ok(1), str::parse) {
+                Ok(n) => self.resume(out, |debugger, steps| {
+                    steps == 0 || (steps < n && !debugger.breaks())
+                })?,
+                Err(_) => writeln!(out, "expected a number of steps")?,
+            },
+            "next" | "n" => {
+                let ip = self.ip();
+                match self.program.jump_target(ip) {
+                    Some(end) if *self.program.ops().cell() == Operation::JumpForward => self
+                        .resume(out, |debugger, steps| {
+                            steps == 0 || (debugger.ip() != end + 1 && !debugger.breaks())
+                        })?,
+                    _ => self.resume(out, |_, steps| steps == 0)?,
+                }
+            }
+            "continue" | "c" => {
+                self.resume(out, |debugger, steps| steps == 0 || !debugger.breaks())?
+            }
+            "break" | "b" => match arg.and_then(|arg| self.resolve(arg)) {
+                Some(ip) => {
+                    self.breakpoints.insert(ip);
+                    writeln!(out, "breakpoint at instruction {}", ip)?;
+                    self.show_at(out, ip)?;
+                }
+                None => writeln!(out, "expected an instruction index or line:column")?,
+            },
+            "delete" | "d" => match arg.and_then(|arg| arg.parse().ok()) {
+                Some(ip) if self.breakpoints.remove(&ip) => {
+                    writeln!(out, "deleted breakpoint at instruction {}", ip)?
+                }
+                _ => writeln!(out, "expected the index of a breakpoint")?,
+            },
+            "breakpoints" => {
+                if self.breakpoints.is_empty() {
+                    writeln!(out, "no breakpoints")?;
+                }
+                for &ip in &self.breakpoints {
+                    writeln!(out, "instruction {} at {}", ip, self.map.spans()[ip])?;
+                }
+            }
+            "where" | "w" => self.show(out)?,
+            "memory" | "m" => match arg.map_or(Ok(RADIUS), str::parse) {
+                Ok(radius) => self.program.memory().dump(out, radius)?,
+                Err(_) => writeln!(out, "expected a number of cells")?,
+            },
+            "help" | "h" => writeln!(out, "{}", HELP)?,
+            "quit" | "q" => return Ok(false),
+            command => writeln!(out, "unknown command `{}`, type `help` for a list", command)?,
+        }
+        Ok(true)
+    }
+
+    /// Index of the next instruction.
+    fn ip(&self) -> usize {
+        self.program.ops().cursor()
+    }
+
+    fn finished(&self) -> bool {
+        self.ip() >= self.map.len()
+    }
+
+    /// Whether the next instruction has a breakpoint on it.
+    fn breaks(&self) -> bool {
+        self.breakpoints.contains(&self.ip())
+    }
+
+    /// Step the program for as long as `go` says to, given how many steps have
+    /// been taken, then show where it stopped.
+    fn resume<O, F>(&mut self, out: &mut O, go: F) -> Result<(), BfError>
+    where
+        O: Write,
+        F: Fn(&Self, u64) -> bool,
+    {
+        if self.stopped || self.finished() {
+            writeln!(out, "the program is not running")?;
+            return Ok(());
+        }
+
+        let mut steps = 0;
+        while !self.finished() && go(self, steps) {
+            if let Err(e) = self.program.step() {
+                self.stopped = true;
+                writeln!(out, "error: {}", e)?;
+                return self.show(out);
+            }
+            steps += 1;
+        }
+
+        if self.finished() {
+            writeln!(out, "program finished after {} steps", self.program.steps())?;
+            Ok(())
+        } else {
+            if self.breaks() && steps > 0 {
+                write!(out, "breakpoint: ")?;
+            }
+            self.show(out)
+        }
+    }
+
+    /// Turn a breakpoint argument into an instruction index.
+    ///
+    /// `line:column` picks the first instruction at or after that position.
+    fn resolve(&self, arg: &str) -> Option<usize> {
+        match arg.split_once(':') {
+            Some((line, column)) => self.map.find(line.parse().ok()?, column.parse().ok()?),
+            None => arg.parse().ok().filter(|&ip| ip < self.map.len()),
+        }
+    }
+
+    /// Show the next instruction in its source.
+    fn show<O: Write>(&self, out: &mut O) -> Result<(), BfError> {
+        if self.finished() {
+            writeln!(out, "at the end of the program")?;
+            return Ok(());
+        }
+        self.show_at(out, self.ip())
+    }
+
+    /// Show instruction `ip` with the source lines around it.
+    fn show_at<O: Write>(&self, out: &mut O, ip: usize) -> Result<(), BfError> {
+        let span = self.map.spans()[ip];
+        let (line, column) = (span.line, span.column);
+        writeln!(
+            out,
+            "instruction {} `{}` at {}",
+            ip,
+            self.lines[line - 1].chars().nth(column - 1).unwrap_or(' '),
+            span
+        )?;
+
+        let first = line.saturating_sub(1).max(1);
+        let last = (line + 1).min(self.lines.len());
+        let width = last.to_string().len();
+        for n in first..=last {
+            let marker = if n == line { '>' } else { ' ' };
+            writeln!(out, "{} {:>width$} | {}", marker, n, self.lines[n - 1])?;
+            if n == line {
+                writeln!(out, "  {:width$} | {:>column$}", "", "^")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_mapped, ParseOptions};
+
+    const SOURCE: &str = "++ set up\n[>+<-]\n>.";
+
+    /// Run `commands` in a debugger for `SOURCE`, returning what it wrote.
+    fn debug(commands: &str) -> String {
+        debug_with(SOURCE, ParseOptions::default(), commands)
+    }
+
+    fn debug_with(source: &str, options: ParseOptions, commands: &str) -> String {
+        let mut map = SourceMap::new("test.bf");
+        let ops = parse_mapped(source.as_bytes(), options, &mut map).unwrap();
+        let mut program = Program::with_io(ops, std::io::empty(), Vec::new());
+        let mut out = Vec::new();
+        Debugger::new(&mut program, source.as_bytes(), &map)
+            .run(commands.as_bytes(), &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn debugger_debug_hash() {
+        let out = debug_with("+#\n#", ParseOptions::default(), "");
+        assert!(out.starts_with("1 instructions"));
+        let out = debug_with("+#\n#", ParseOptions { debug_hash: true }, "b 2:1\n");
+        assert!(out.starts_with("3 instructions"));
+        assert!(out.contains("breakpoint at instruction 2\ninstruction 2 `#` at 2:1\n"));
+    }
+
+    #[test]
+    fn debugger_steps() {
+        let out = debug("step\n\nnext\nstep\nc\nstep\n");
+        assert!(out.contains("instruction 1 `+` at 1:2\n> 1 | ++ set up\n    |  ^\n  2 | [>+<-]\n"));
+        assert!(out.contains("instruction 2 `[` at 2:1\n"));
+        assert!(out.contains("instruction 8 `>` at 3:1\n"));
+        assert!(out.contains("program finished after 15 steps\n"));
+        assert!(out.ends_with("the program is not running\n(bf) \n"));
+    }
+
+    #[test]
+    fn debugger_breakpoints() {
+        let out = debug("b 2:4\nb 1:3\nbreakpoints\nc\nc\ndelete 3\nc\n");
+        assert!(out.contains("breakpoint at instruction 5\n"));
+        assert!(out.contains("breakpoint at instruction 2\n"));
+        assert!(out.contains("instruction 2 at 2:1\ninstruction 5 at 2:4\n"));
+        assert!(out.contains("breakpoint: instruction 5 `<` at 2:4\n"));
+        assert!(out.contains("expected the index of a breakpoint\n"));
+        assert_eq!(out.matches("breakpoint: instruction 5").count(), 2);
+    }
+
+    #[test]
+    fn debugger_memory() {
+        let out = debug("s 3\nm 2\n");
+        assert!(out.contains("cell  0  1  2\nhex  02 00 00\ndec   2  0  0\n      ^\n"));
+        let out = debug("s 3\nm -1\nm 9223372036854775807\n");
+        assert!(out.contains("expected a number of cells"));
+        assert!(out.contains("(bf) cell   0   1   2   3"));
+    }
+
+    #[test]
+    fn debugger_reports_errors() {
+        let out = debug_with("<", ParseOptions::default(), "s\ns\nq\ns\n");
+        assert!(out.contains("error: pointer moved off the tape at instruction 0\n"));
+        assert!(out.ends_with("the program is not running\n(bf) "));
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt::{self, Write};
+
+use crate::parse::tokens;
+use crate::{Operation, ParseOptions, Span};
+
+/// How bad a `Diagnostic` is.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Level {
+    /// The program can't be run.
+    Error,
+    /// The program runs, but probably not as intended.
+    Warning,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Error => write!(f, "error"),
+            Level::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem with a program's source, with where it is and what might fix it.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Diagnostic {
+    pub level: Level,
+    /// What is wrong.
+    pub message: String,
+    /// Where it is wrong.
+    pub span: Span,
+    /// Shown under the source at `span`.
+    pub label: String,
+    /// Another place worth looking at, the likely partner of an unmatched bracket.
+    pub related: Option<(Span, String)>,
+}
+
+impl Diagnostic {
+    /// Format the diagnostic like a compiler would, quoting the lines of
+    /// `source` it is about.
+    pub fn render(&self, file: &str, source: &[u8]) -> String {
+        let source = String::from_utf8_lossy(source);
+        let lines: Vec<_> = source.lines().collect();
+
+        // The primary span is marked with `^`, the related one with `-`
+        let mut marks = vec![(self.span, '^', self.label.as_str())];
+        if let Some((span, label)) = &self.related {
+            marks.push((*span, '-', label.as_str()));
+        }
+        marks.sort_by_key(|(span, ..)| span.offset);
+
+        let width = marks.iter().map(|(span, ..)| span.line).max().unwrap_or(0);
+        let width = width.to_string().len();
+        let mut out = String::new();
+        let _ = writeln!(out, "{}: {}", self.level, self.message);
+        let _ = writeln!(out, "{:width$}--> {}:{}", "", file, self.span);
+        let _ = writeln!(out, "{:width$} |", "");
+        let mut shown = None;
+        for (span, mark, label) in marks {
+            let text = lines.get(span.line - 1).copied().unwrap_or("");
+            if shown != Some(span.line) {
+                if shown.is_some_and(|line| span.line > line + 1) {
+                    let _ = writeln!(out, "...");
+                }
+                let _ = writeln!(out, "{:>width$} | {}", span.line, text);
+                shown = Some(span.line);
+            }
+            // Keep tabs so the mark lines up with the character above it
+            let indent: String = text
+                .chars()
+                .take(span.column - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let _ = writeln!(out, "{:width$} | {}{} {}", "", indent, mark, label);
+        }
+        out
+    }
+}
+
+/// Find every unmatched bracket in `source`.
+///
+/// Unlike `parse`, this doesn't stop at the first one.
+pub fn diagnose(source: &[u8], options: ParseOptions) -> Vec<Diagnostic> {
+    let tokens: Vec<_> = tokens(source, options).filter_map(Result::ok).collect();
+    // The other half of every matched bracket, by token index
+    let mut partners = vec![None; tokens.len()];
+    let mut open = Vec::new();
+    let mut unmatched = Vec::new();
+
+    for (i, (op, _)) in tokens.iter().enumerate() {
+        match op {
+            Operation::JumpForward => open.push(i),
+            Operation::JumpBack => match open.pop() {
+                Some(j) => {
+                    partners[i] = Some(j);
+                    partners[j] = Some(i);
+                }
+                None => unmatched.push(i),
+            },
+            _ => {}
+        }
+    }
+    unmatched.extend(open);
+    unmatched.sort_unstable();
+
+    let span = |i: usize| tokens[i].1;
+    unmatched
+        .into_iter()
+        .map(|i| {
+            if tokens[i].0 == Operation::JumpForward {
+                // The first `]` after it was taken by a `[` nested inside it
+                let close = (i + 1..tokens.len()).find(|&j| tokens[j].0 == Operation::JumpBack);
+                Diagnostic {
+                    level: Level::Error,
+                    message: "unmatched `[`".to_string(),
+                    span: span(i),
+                    label: "this loop is never closed".to_string(),
+                    related: close.and_then(|j| {
+                        let open = partners[j]?;
+                        Some((
+                            span(j),
+                            format!("the nearest `]` closes the `[` at {}", span(open)),
+                        ))
+                    }),
+                }
+            } else {
+                // Every `[` before it is already closed
+                let open = (0..i)
+                    .rev()
+                    .find(|&j| tokens[j].0 == Operation::JumpForward);
+                Diagnostic {
+                    level: Level::Error,
+                    message: "unmatched `]`".to_string(),
+                    span: span(i),
+                    label: "there is no loop to close".to_string(),
+                    related: open.and_then(|j| {
+                        let close = partners[j]?;
+                        Some((
+                            span(j),
+                            format!("the nearest `[` is closed at {}", span(close)),
+                        ))
+                    }),
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(source: &str) -> Vec<(String, String, Option<String>)> {
+        diagnose(source.as_bytes(), ParseOptions::default())
+            .into_iter()
+            .map(|d| {
+                let related = d.related.map(|(span, _)| span.to_string());
+                (d.message, d.span.to_string(), related)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diagnose_balanced() {
+        assert!(diagnose(b"+[->[-]<]", ParseOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn diagnose_reports_every_bracket() {
+        let s = |s: &str| s.to_string();
+        assert_eq!(
+            positions("]+\n[[-]\n]]"),
+            [
+                (s("unmatched `]`"), s("1:1"), None),
+                (s("unmatched `]`"), s("3:2"), Some(s("2:2"))),
+            ]
+        );
+        assert_eq!(
+            positions("[\n[[-]"),
+            [
+                (s("unmatched `[`"), s("1:1"), Some(s("2:4"))),
+                (s("unmatched `[`"), s("2:1"), Some(s("2:4"))),
+            ]
+        );
+        assert_eq!(positions("+["), [(s("unmatched `[`"), s("1:2"), None)]);
+    }
+
+    #[test]
+    fn diagnostic_render() {
+        let source = b"+[\n>+<-\n\t[-]";
+        let diagnostics = diagnose(source, ParseOptions::default());
+        assert_eq!(
+            diagnostics[0].render("test.bf", source),
+            "error: unmatched `[`
+ --> test.bf:1:2
+  |
+1 | +[
+  |  ^ this loop is never closed
+...
+3 | \t[-]
+  | \t  - the nearest `]` closes the `[` at 3:2
+"
+        );
+
+        let source = b"[-]]";
+        let diagnostics = diagnose(source, ParseOptions::default());
+        assert_eq!(
+            diagnostics[0].render("test.bf", source),
+            "error: unmatched `]`
+ --> test.bf:1:4
+  |
+1 | [-]]
+  | - the nearest `[` is closed at 1:3
+  |    ^ there is no loop to close
+"
+        );
+    }
+}
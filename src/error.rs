@@ -0,0 +1,77 @@
+use std::fmt;
+use std::io;
+
+use crate::Limit;
+
+/// Everything that can go wrong while loading or running a program.
+#[derive(Debug)]
+pub enum BfError {
+    /// A `[` at the given instruction index has no matching `]`.
+    UnmatchedOpen(usize),
+    /// A `]` at the given instruction index has no matching `[`.
+    UnmatchedClose(usize),
+    /// The pointer was moved off the edge of the tape, by the operation at the
+    /// given instruction index if it is known.
+    PointerOutOfBounds(Option<usize>),
+    /// One of the `Limits` was hit, by the operation at the given instruction
+    /// index if it is known.
+    LimitExceeded(Limit, Option<usize>),
+    /// `,` read something that isn't a valid cell value.
+    InputError(String),
+    /// Reading the source or program input, or writing output, failed.
+    IoError(io::Error),
+}
+
+impl fmt::Display for BfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BfError::UnmatchedOpen(i) => write!(f, "unmatched `[` at instruction {}", i),
+            BfError::UnmatchedClose(i) => write!(f, "unmatched `]` at instruction {}", i),
+            BfError::PointerOutOfBounds(Some(i)) => {
+                write!(f, "pointer moved off the tape at instruction {}", i)
+            }
+            BfError::PointerOutOfBounds(None) => write!(f, "pointer moved off the tape"),
+            BfError::LimitExceeded(limit, Some(i)) => {
+                write!(f, "{} limit exceeded at instruction {}", limit, i)
+            }
+            BfError::LimitExceeded(limit, None) => write!(f, "{} limit exceeded", limit),
+            BfError::InputError(s) => write!(f, "invalid input {:?}", s),
+            BfError::IoError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl BfError {
+    /// Blame the instruction at `ip` for an error that doesn't say where it happened.
+    pub(crate) fn at(self, ip: usize) -> Self {
+        match self {
+            BfError::PointerOutOfBounds(None) => BfError::PointerOutOfBounds(Some(ip)),
+            BfError::LimitExceeded(limit, None) => BfError::LimitExceeded(limit, Some(ip)),
+            e => e,
+        }
+    }
+
+    /// Index of the instruction the error is about, if it is known.
+    pub fn instruction(&self) -> Option<usize> {
+        match *self {
+            BfError::UnmatchedOpen(i) | BfError::UnmatchedClose(i) => Some(i),
+            BfError::PointerOutOfBounds(i) | BfError::LimitExceeded(_, i) => i,
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for BfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BfError::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BfError {
+    fn from(e: io::Error) -> Self {
+        BfError::IoError(e)
+    }
+}
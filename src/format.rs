@@ -0,0 +1,183 @@
+use crate::{parse_with, BfError, Operation, ParseOptions};
+
+/// Indentation for each level of loop nesting.
+const INDENT: &str = "  ";
+
+/// Lay out brainfuck source with every `[` and `]` on its own line and the
+/// body of each loop indented one level further.
+///
+/// Runs of the same instruction are grouped together and separated from
+/// other runs by a space. Comments stay where they were, on their own line
+/// or next to the instructions around them. Line breaks in the source are
+/// kept, but runs of blank lines are squeezed into one. Formatting the result
+/// again gives the same text.
+///
+/// Fails if the brackets are unbalanced, as there'd be no telling how to
+/// indent the rest.
+pub fn format(source: &[u8], options: ParseOptions) -> Result<String, BfError> {
+    parse_with(source, options)?;
+
+    let mut formatter = Formatter::default();
+    for c in String::from_utf8_lossy(source).chars() {
+        let op = match c {
+            '#' if options.debug_hash => Operation::DebugDump,
+            c => Operation::from(c),
+        };
+        match op {
+            Operation::NoOp if c == '\n' => formatter.newline(),
+            Operation::NoOp => formatter.comment.push(c),
+            Operation::JumpForward => {
+                formatter.line_break();
+                formatter.bracket('[');
+                formatter.depth += 1;
+            }
+            Operation::JumpBack => {
+                formatter.line_break();
+                formatter.depth -= 1;
+                formatter.bracket(']');
+            }
+            _ => formatter.instruction(c),
+        }
+    }
+    formatter.line_break();
+
+    // A blank line is only added once the next line starts, so there can only be one at the end
+    let mut out = formatter.out;
+    if out.ends_with("\n\n") {
+        out.pop();
+    }
+    Ok(out)
+}
+
+#[derive(Default)]
+struct Formatter {
+    out: String,
+    /// Pieces of the line being built, runs, brackets and comments
+    line: Vec<String>,
+    /// Loop nesting of the line being built
+    indent: usize,
+    /// Loop nesting of the next instruction
+    depth: usize,
+    /// Whether the line being built is a `[` or `]`, which only comments can follow
+    bracket: bool,
+    /// Text between instructions that hasn't been added to the line yet
+    comment: String,
+    /// Whether anything has been seen since the last line break in the source
+    blank: bool,
+}
+
+impl Formatter {
+    /// Move a finished comment onto the line.
+    fn end_comment(&mut self) {
+        let comment = self.comment.trim();
+        if !comment.is_empty() {
+            self.blank = false;
+            if self.line.is_empty() {
+                self.indent = self.depth;
+            }
+            self.line.push(comment.to_string());
+        }
+        self.comment.clear();
+    }
+
+    /// Write out the line being built, if there is one.
+    fn line_break(&mut self) {
+        self.end_comment();
+        if !self.line.is_empty() {
+            for _ in 0..self.indent {
+                self.out.push_str(INDENT);
+            }
+            self.out.push_str(&self.line.join(" "));
+            self.out.push('\n');
+            self.line.clear();
+        }
+        self.bracket = false;
+    }
+
+    fn bracket(&mut self, c: char) {
+        self.blank = false;
+        self.indent = self.depth;
+        self.line.push(c.to_string());
+        self.bracket = true;
+    }
+
+    fn instruction(&mut self, c: char) {
+        self.blank = false;
+        if self.bracket {
+            self.line_break();
+        }
+        self.end_comment();
+        if self.line.is_empty() {
+            self.indent = self.depth;
+        }
+        match self.line.last_mut() {
+            Some(run) if run.starts_with(c) && run.chars().all(|r| r == c) => run.push(c),
+            _ => self.line.push(c.to_string()),
+        }
+    }
+
+    /// A line break in the source, kept as one in the output.
+    fn newline(&mut self) {
+        let blank = self.blank && self.comment.trim().is_empty();
+        self.line_break();
+        // Keep a single blank line between paragraphs, but none at the start
+        if blank && !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+        self.blank = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn fmt(source: &str) -> String {
+        format(source.as_bytes(), ParseOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn format_indents_loops() {
+        assert_eq!(
+            fmt("++[>+++[-]<-]>."),
+            "++\n[\n  > +++\n  [\n    -\n  ]\n  < -\n]\n> .\n"
+        );
+    }
+
+    #[test]
+    fn format_keeps_comments() {
+        assert_eq!(
+            fmt("set up ++  +\n\n\n\n[ loop\n  >+<- move it  ] done\n# the end"),
+            "set up +++\n\n[ loop\n  > + < - move it\n] done\n# the end\n"
+        );
+    }
+
+    #[test]
+    fn format_debug_hash() {
+        let options = ParseOptions { debug_hash: true };
+        assert_eq!(format(b"+##-", options).unwrap(), "+ ## -\n");
+        assert_eq!(fmt("+##-"), "+ ## -\n");
+    }
+
+    #[test]
+    fn format_is_idempotent() {
+        let rot13 = include_str!("../tests/programs/rot13.bf");
+        for source in ["", "\n\n+\n\n", "a[b[c]d]e", "+[->+<]\n\n\n>.", rot13] {
+            let once = fmt(source);
+            assert_eq!(fmt(&once), once);
+            assert_eq!(
+                parse(once.as_bytes()).unwrap(),
+                parse(source.as_bytes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn format_unbalanced() {
+        assert!(matches!(
+            format(b"[[]", ParseOptions::default()),
+            Err(BfError::UnmatchedOpen(0))
+        ));
+    }
+}
@@ -0,0 +1,426 @@
+use crate::{BfError, Operation};
+
+/// A folded form of the operation stream.
+///
+/// Runs of `+`/`-` and `<`/`>` become a single instruction, and pointer moves
+/// between cell updates are deferred into offsets so a block like `>>+<<` does
+/// not move the pointer at all.
+///
+/// Each instruction is paired with the index of the first operation it was
+/// folded from, so errors can be traced back to the source.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Ir {
+    /// Add to the current cell, wrapping.
+    Add(i32),
+    /// Move the pointer.
+    Move(isize),
+    /// Add to the cell `offset` away from the pointer, wrapping.
+    AddAt { offset: isize, delta: i32 },
+    /// Make sure the cells from `low` to `high` away from the pointer are on
+    /// the tape, as a deferred move passed over them without touching them.
+    Check { low: isize, high: isize },
+    /// Write the current cell.
+    Output,
+    /// Read into the current cell.
+    Input,
+    /// Repeat the body while the current cell is not zero.
+    Loop(Vec<(Ir, usize)>),
+    /// Set the current cell to zero, `[-]`.
+    SetZero,
+    /// Add the current cell times `factor` to the cell `offset` away, `[->++<]`.
+    ///
+    /// Always followed by a `SetZero` for the counter cell.
+    MulAdd { offset: isize, factor: i32 },
+    /// Move right until the current cell is zero, `[>]`.
+    ScanRight,
+    /// Move left until the current cell is zero, `[<]`.
+    ScanLeft,
+    /// Dump the memory around the pointer, `#`.
+    DebugDump,
+}
+
+/// Fold `ops` into IR.
+///
+/// Fails if the brackets are unbalanced.
+pub fn lower(ops: &[Operation]) -> Result<Vec<(Ir, usize)>, BfError> {
+    // One block per open loop, the first is the top level
+    let mut blocks = vec![Block::new(0)];
+    // Indices of the `[`s still waiting for a `]`
+    let mut open = Vec::new();
+
+    for (i, op) in ops.iter().enumerate() {
+        let block = blocks.last_mut().unwrap();
+        match op {
+            Operation::Increment => block.add(1, i),
+            Operation::Decrement => block.add(-1, i),
+            Operation::MoveRight => block.mv(1),
+            Operation::MoveLeft => block.mv(-1),
+            Operation::Output => block.push(Ir::Output, i),
+            Operation::Input => block.push(Ir::Input, i),
+            Operation::DebugDump => block.push(Ir::DebugDump, i),
+            Operation::JumpForward => {
+                block.flush(i);
+                blocks.push(Block::new(i + 1));
+                open.push(i);
+            }
+            Operation::JumpBack => {
+                open.pop().ok_or(BfError::UnmatchedClose(i))?;
+                let body = blocks.pop().unwrap().finish(i);
+                blocks.last_mut().unwrap().emit(Ir::Loop(body), i);
+            }
+            Operation::NoOp => {}
+        }
+    }
+
+    match open.pop() {
+        Some(i) => Err(BfError::UnmatchedOpen(i)),
+        None => Ok(blocks.pop().unwrap().finish(ops.len().saturating_sub(1))),
+    }
+}
+
+/// Rewrite common loop idioms into dedicated instructions.
+pub fn optimize(ir: Vec<(Ir, usize)>) -> Vec<(Ir, usize)> {
+    let mut out = Vec::with_capacity(ir.len());
+    for (instr, at) in ir {
+        match instr {
+            Ir::Loop(body) => optimize_loop(optimize(body), at, &mut out),
+            _ => out.push((instr, at)),
+        }
+    }
+    out
+}
+
+/// Push the rewritten form of a loop at `at` with an already optimized body onto `out`.
+fn optimize_loop(body: Vec<(Ir, usize)>, at: usize, out: &mut Vec<(Ir, usize)>) {
+    match body.as_slice() {
+        // Any odd step reaches zero eventually
+        [(Ir::Add(d), _)] if d % 2 != 0 => out.push((Ir::SetZero, at)),
+        [(Ir::Move(1), _)] => out.push((Ir::ScanRight, at)),
+        [(Ir::Move(-1), _)] => out.push((Ir::ScanLeft, at)),
+        _ => match mul_adds(&body) {
+            Some(muls) => {
+                out.extend(muls.into_iter().map(|mul| (mul, at)));
+                out.push((Ir::SetZero, at));
+            }
+            None => out.push((Ir::Loop(body), at)),
+        },
+    }
+}
+
+/// Turn a loop body that steps its counter by one and only adds to other cells into `MulAdd`s.
+fn mul_adds(body: &[(Ir, usize)]) -> Option<Vec<Ir>> {
+    let mut step = 0;
+    let mut muls = Vec::new();
+    for (instr, _) in body {
+        match instr {
+            Ir::Add(d) => step += d,
+            Ir::AddAt { offset, delta } => muls.push((*offset, *delta)),
+            _ => return None,
+        }
+    }
+
+    // Counting down runs the body `cell` times, counting up runs it `-cell` times
+    let sign = match step {
+        -1 => 1,
+        1 => -1,
+        _ => return None,
+    };
+    Some(
+        muls.into_iter()
+            .map(|(offset, delta)| Ir::MulAdd {
+                offset,
+                factor: delta * sign,
+            })
+            .collect(),
+    )
+}
+
+/// IR for a straight run of operations, with the pointer movement not yet emitted.
+struct Block {
+    ir: Vec<(Ir, usize)>,
+    offset: isize,
+    /// Index of the first operation not folded into `ir` yet
+    start: usize,
+    /// Lowest and highest offsets the pointer has reached since it was last moved
+    reached: (isize, isize),
+    /// Where the instructions since the pointer was last moved start in `ir`
+    since_move: usize,
+}
+
+impl Block {
+    fn new(start: usize) -> Self {
+        Self {
+            ir: Vec::new(),
+            offset: 0,
+            start,
+            reached: (0, 0),
+            since_move: 0,
+        }
+    }
+
+    /// Push `ir` for the operations from `start` to `i`.
+    fn emit(&mut self, ir: Ir, i: usize) {
+        self.ir.push((ir, self.start));
+        self.start = i + 1;
+    }
+
+    /// Defer moving the pointer by `n`.
+    fn mv(&mut self, n: isize) {
+        self.offset += n;
+        self.reached = (
+            self.reached.0.min(self.offset),
+            self.reached.1.max(self.offset),
+        );
+    }
+
+    /// Add `delta` to the cell at the pending offset for operation `i`, merging
+    /// with the previous add if possible.
+    fn add(&mut self, delta: i32, i: usize) {
+        let offset = self.offset;
+        self.check(Some(offset), i);
+        match self.ir.last_mut() {
+            Some((Ir::Add(d), _)) if offset == 0 => *d += delta,
+            Some((
+                Ir::AddAt {
+                    offset: o,
+                    delta: d,
+                },
+                _,
+            )) if *o == offset => *d += delta,
+            _ if offset == 0 => self.emit(Ir::Add(delta), i),
+            _ => self.emit(Ir::AddAt { offset, delta }, i),
+        }
+        self.start = i + 1;
+
+        // Drop adds that cancelled out, the moves they covered may need checking again
+        if let Some((Ir::Add(0), at)) | Some((Ir::AddAt { delta: 0, .. }, at)) = self.ir.last() {
+            self.start = *at;
+            self.ir.pop();
+        }
+    }
+
+    /// Emit a `Check` before operation `i` if the pointer has passed over
+    /// cells that nothing since it last moved has touched, unless the next
+    /// instruction reaches the furthest of them at `access` anyway.
+    ///
+    /// A deferred move could otherwise skip a cell off the tape that the
+    /// operations it came from would have failed on.
+    fn check(&mut self, access: Option<isize>, i: usize) {
+        // Touching a cell means every cell between it and the pointer is on the tape
+        let (low, high) = self.ir[self.since_move..]
+            .iter()
+            .filter_map(|(ir, _)| match ir {
+                Ir::AddAt { offset, .. } => Some((*offset, *offset)),
+                Ir::Check { low, high } => Some((*low, *high)),
+                _ => None,
+            })
+            .chain(access.map(|offset| (offset, offset)))
+            .fold((0, 0), |(low, high), (l, h)| (low.min(l), high.max(h)));
+        let (reached_low, reached_high) = self.reached;
+        if reached_low < low || reached_high > high {
+            self.ir.push((
+                Ir::Check {
+                    low: reached_low,
+                    high: reached_high,
+                },
+                self.start,
+            ));
+            self.start = i;
+        }
+    }
+
+    /// Emit an instruction for operation `i` that needs the pointer to be on its cell.
+    fn push(&mut self, ir: Ir, i: usize) {
+        self.flush(i);
+        self.emit(ir, i);
+    }
+
+    /// Emit the pending pointer movement, before operation `i`.
+    fn flush(&mut self, i: usize) {
+        self.check(Some(self.offset), i);
+        if self.offset != 0 {
+            self.ir.push((Ir::Move(self.offset), self.start.min(i)));
+            self.offset = 0;
+        }
+        self.start = i;
+        self.reached = (0, 0);
+        self.since_move = self.ir.len();
+    }
+
+    /// The finished IR, with operation `end` the last in the block.
+    fn finish(mut self, end: usize) -> Vec<(Ir, usize)> {
+        self.flush(end);
+        self.ir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn lower_src(src: &str) -> Vec<(Ir, usize)> {
+        lower(&parse(src.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn ir_folds_runs() {
+        assert_eq!(
+            lower_src("+++>>--<"),
+            vec![
+                (Ir::Add(3), 0),
+                (
+                    Ir::AddAt {
+                        offset: 2,
+                        delta: -2
+                    },
+                    3
+                ),
+                (Ir::Move(1), 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn ir_cancels() {
+        assert_eq!(lower_src("+-"), vec![]);
+        assert_eq!(
+            lower_src(">+<"),
+            vec![(
+                Ir::AddAt {
+                    offset: 1,
+                    delta: 1
+                },
+                0
+            )]
+        );
+    }
+
+    #[test]
+    fn ir_checks_skipped_cells() {
+        assert_eq!(lower_src("<>"), vec![(Ir::Check { low: -1, high: 0 }, 0)]);
+        assert_eq!(
+            lower_src("+<>+"),
+            vec![
+                (Ir::Add(1), 0),
+                (Ir::Check { low: -1, high: 0 }, 1),
+                (Ir::Add(1), 3)
+            ]
+        );
+        assert_eq!(
+            lower_src(">>><"),
+            vec![(Ir::Check { low: 0, high: 3 }, 0), (Ir::Move(2), 3)]
+        );
+        // An add that cancels out no longer touches its cell
+        assert_eq!(lower_src("<+->"), vec![(Ir::Check { low: -1, high: 0 }, 0)]);
+        // Touching the furthest cell is check enough
+        assert_eq!(lower_src(">>+<<>").len(), 2);
+        assert_eq!(lower_src("<<"), vec![(Ir::Move(-2), 0)]);
+    }
+
+    #[test]
+    fn ir_flushes_before_io_and_loops() {
+        assert_eq!(
+            lower_src(">.>[-<]"),
+            vec![
+                (Ir::Move(1), 0),
+                (Ir::Output, 1),
+                (Ir::Move(1), 2),
+                (Ir::Loop(vec![(Ir::Add(-1), 4), (Ir::Move(-1), 5)]), 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn ir_optimize_clear() {
+        assert_eq!(
+            optimize(lower_src("[-]>[+++]")),
+            vec![(Ir::SetZero, 0), (Ir::Move(1), 3), (Ir::SetZero, 4)]
+        );
+        assert_eq!(optimize(lower_src("[--]")), lower_src("[--]"));
+    }
+
+    #[test]
+    fn ir_optimize_mul() {
+        assert_eq!(
+            optimize(lower_src("[->++>+++<<]")),
+            vec![
+                (
+                    Ir::MulAdd {
+                        offset: 1,
+                        factor: 2
+                    },
+                    0
+                ),
+                (
+                    Ir::MulAdd {
+                        offset: 2,
+                        factor: 3
+                    },
+                    0
+                ),
+                (Ir::SetZero, 0),
+            ]
+        );
+        assert_eq!(optimize(lower_src("[-+-]")), vec![(Ir::SetZero, 0)]);
+        assert_eq!(
+            optimize(lower_src("[+<->]")),
+            vec![
+                (
+                    Ir::MulAdd {
+                        offset: -1,
+                        factor: 1
+                    },
+                    0
+                ),
+                (Ir::SetZero, 0)
+            ]
+        );
+        assert_eq!(optimize(lower_src("[->+<.]")), lower_src("[->+<.]"));
+        // The loop has to step off the tape to find out whether it can
+        assert_eq!(optimize(lower_src("[<>-]")), lower_src("[<>-]"));
+    }
+
+    #[test]
+    fn ir_optimize_scan() {
+        assert_eq!(
+            optimize(lower_src("[>]<[<]")),
+            vec![(Ir::ScanRight, 0), (Ir::Move(-1), 3), (Ir::ScanLeft, 4)]
+        );
+        assert_eq!(optimize(lower_src("[>>]")), lower_src("[>>]"));
+    }
+
+    #[test]
+    fn ir_optimize_nested() {
+        assert_eq!(
+            optimize(lower_src("[>[-]<-]")),
+            vec![(
+                Ir::Loop(vec![
+                    (Ir::Move(1), 1),
+                    (Ir::SetZero, 2),
+                    (
+                        Ir::AddAt {
+                            offset: -1,
+                            delta: -1
+                        },
+                        5
+                    ),
+                    (Ir::Move(-1), 7),
+                ]),
+                0
+            )]
+        );
+    }
+
+    #[test]
+    fn ir_unmatched() {
+        assert!(matches!(
+            lower(&[Operation::JumpForward]),
+            Err(BfError::UnmatchedOpen(0))
+        ));
+        assert!(matches!(
+            lower(&[Operation::JumpBack]),
+            Err(BfError::UnmatchedClose(0))
+        ));
+    }
+}
@@ -0,0 +1,378 @@
+//! Compiles `Ir` to x86-64 machine code and runs it directly on the memory tape.
+//!
+//! The generated function keeps the tape base in `r12`, the cursor in `r13`,
+//! the tape length in `r14` and the `Context` in `rbx`. Anything that needs
+//! Rust (I/O, growing the tape, errors) goes through the `extern "C"` callbacks
+//! below, which keep the `Program` in sync. Before calling one, the code
+//! records which instruction it is running, so errors can be traced back to
+//! the source.
+use std::io::prelude::*;
+
+use crate::ir::Ir;
+use crate::{BfError, Limit, Program};
+
+/// State shared between the generated code and the callbacks.
+///
+/// The generated code reads `base` and `len` and writes `at` at fixed offsets,
+/// so they must stay first.
+#[repr(C)]
+struct Context<'p, R, W> {
+    base: *mut u8,
+    len: usize,
+    /// Index of the first operation the running instruction was folded from
+    at: usize,
+    program: &'p mut Program<R, W>,
+    error: Option<BfError>,
+}
+
+impl<R: Read, W: Write> Context<'_, R, W> {
+    /// Refresh `base` and `len` after the tape may have been reallocated.
+    fn sync(&mut self) {
+        let (base, len) = self.program.memory_mut().raw_parts();
+        self.base = base;
+        self.len = len;
+    }
+
+    /// Record the outcome of a callback, returning the status the generated code expects.
+    fn status(&mut self, result: Result<(), BfError>) -> u64 {
+        self.sync();
+        match result {
+            Ok(()) => 0,
+            Err(e) => {
+                self.error = Some(e);
+                1
+            }
+        }
+    }
+}
+
+/// Make sure the cell at `index` exists, growing the tape if needed.
+extern "C" fn ensure<R: Read, W: Write>(ctx: *mut Context<R, W>, index: isize) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    let memory = ctx.program.memory_mut();
+    let offset = index - memory.cursor() as isize;
+    let result = memory.offset_mut(offset).map(|_| ());
+    ctx.status(result)
+}
+
+extern "C" fn output<R: Read, W: Write>(ctx: *mut Context<R, W>, index: usize) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.program.memory_mut().seek(index);
+    let result = ctx.program.prt();
+    ctx.status(result)
+}
+
+extern "C" fn input<R: Read, W: Write>(ctx: *mut Context<R, W>, index: usize) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.program.memory_mut().seek(index);
+    let result = ctx.program.inp();
+    ctx.status(result)
+}
+
+extern "C" fn dump<R: Read, W: Write>(ctx: *mut Context<R, W>, index: usize) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.program.memory_mut().seek(index);
+    let result = ctx.program.dmp();
+    ctx.status(result)
+}
+
+/// Compile `ir` and run it against the memory of `program`.
+pub(crate) fn run<R: Read, W: Write>(
+    program: &mut Program<R, W>,
+    ir: &[(Ir, usize)],
+) -> Result<(), BfError> {
+    let mut asm = Assembler::new(Callbacks {
+        ensure: ensure::<R, W> as *const () as u64,
+        output: output::<R, W> as *const () as u64,
+        input: input::<R, W> as *const () as u64,
+        dump: dump::<R, W> as *const () as u64,
+    });
+    asm.prologue();
+    asm.block(ir);
+    asm.epilogue();
+
+    let code = ExecutableBuffer::new(&asm.code)?;
+    let start = program.memory_mut().cursor();
+    let mut ctx = Context {
+        base: std::ptr::null_mut(),
+        len: 0,
+        at: 0,
+        program,
+        error: None,
+    };
+    ctx.sync();
+
+    // Safety: the buffer holds a complete function with this signature, and
+    // only touches memory through `ctx`, which outlives the call.
+    let f: extern "C" fn(*mut Context<R, W>, usize) -> usize =
+        unsafe { std::mem::transmute(code.ptr) };
+    let end = f(&mut ctx, start);
+
+    ctx.program.memory_mut().seek(end);
+    match ctx.error.take() {
+        Some(
+            e @ (BfError::PointerOutOfBounds(None) | BfError::LimitExceeded(Limit::Cells, None)),
+        ) => Err(ctx.program.replay(e, ctx.at)),
+        Some(e) => Err(e.at(ctx.at)),
+        None => Ok(()),
+    }
+}
+
+/// Addresses of the callbacks for one `Program<R, W>`.
+struct Callbacks {
+    ensure: u64,
+    output: u64,
+    input: u64,
+    dump: u64,
+}
+
+/// Emits the machine code for a block of IR.
+struct Assembler {
+    code: Vec<u8>,
+    callbacks: Callbacks,
+    /// Positions of `rel32` jumps to the shared error exit
+    errors: Vec<usize>,
+    /// `at` of the instruction being emitted
+    at: usize,
+}
+
+impl Assembler {
+    fn new(callbacks: Callbacks) -> Self {
+        Self {
+            code: Vec::new(),
+            callbacks,
+            errors: Vec::new(),
+            at: 0,
+        }
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    /// Emit a jump with a `rel32` to be filled in by `patch`, returning its position.
+    fn jump(&mut self, opcode: &[u8]) -> usize {
+        self.emit(opcode);
+        self.emit(&[0; 4]);
+        self.code.len() - 4
+    }
+
+    /// Point the `rel32` at `at` to `target`.
+    fn patch(&mut self, at: usize, target: usize) {
+        let rel = target as i32 - (at + 4) as i32;
+        self.code[at..at + 4].copy_from_slice(&rel.to_le_bytes());
+    }
+
+    fn prologue(&mut self) {
+        // push rbx, r12, r13, r14, r15 (r15 keeps the stack 16-byte aligned for calls)
+        self.emit(&[0x53, 0x41, 0x54, 0x41, 0x55, 0x41, 0x56, 0x41, 0x57]);
+        // mov rbx, rdi
+        self.emit(&[0x48, 0x89, 0xFB]);
+        // mov r13, rsi
+        self.emit(&[0x49, 0x89, 0xF5]);
+        self.reload();
+    }
+
+    fn epilogue(&mut self) {
+        // Errors leave the cursor where it was too, the callback has kept the error
+        let end = self.code.len();
+        for at in std::mem::take(&mut self.errors) {
+            self.patch(at, end);
+        }
+        // mov rax, r13
+        self.emit(&[0x4C, 0x89, 0xE8]);
+        // pop r15, r14, r13, r12, rbx
+        self.emit(&[0x41, 0x5F, 0x41, 0x5E, 0x41, 0x5D, 0x41, 0x5C, 0x5B]);
+        // ret
+        self.emit(&[0xC3]);
+    }
+
+    /// Load the tape base and length from the context.
+    fn reload(&mut self) {
+        // mov r12, [rbx]
+        self.emit(&[0x4C, 0x8B, 0x23]);
+        // mov r14, [rbx + 8]
+        self.emit(&[0x4C, 0x8B, 0x73, 0x08]);
+    }
+
+    /// Call `f(ctx, rsi)` and bail out to the error exit if it fails.
+    fn call(&mut self, f: u64) {
+        // mov qword [rbx + 16], at
+        self.emit(&[0x48, 0xC7, 0x43, 0x10]);
+        self.emit(&(self.at as u32).to_le_bytes());
+        // mov rdi, rbx
+        self.emit(&[0x48, 0x89, 0xDF]);
+        // mov rax, f
+        self.emit(&[0x48, 0xB8]);
+        self.emit(&f.to_le_bytes());
+        // call rax
+        self.emit(&[0xFF, 0xD0]);
+        // test rax, rax
+        self.emit(&[0x48, 0x85, 0xC0]);
+        // jnz error
+        let at = self.jump(&[0x0F, 0x85]);
+        self.errors.push(at);
+        self.reload();
+    }
+
+    /// Leave `rax` holding the index `offset` cells from the cursor, growing the tape if needed.
+    fn address(&mut self, offset: isize) {
+        self.lea(offset);
+        // cmp rax, r14
+        self.emit(&[0x4C, 0x39, 0xF0]);
+        // jb ok
+        let ok = self.jump(&[0x0F, 0x82]);
+        // mov rsi, rax
+        self.emit(&[0x48, 0x89, 0xC6]);
+        self.call(self.callbacks.ensure);
+        self.lea(offset);
+        let end = self.code.len();
+        self.patch(ok, end);
+    }
+
+    fn lea(&mut self, offset: isize) {
+        // lea rax, [r13 + offset]
+        self.emit(&[0x49, 0x8D, 0x85]);
+        self.emit(&(offset as i32).to_le_bytes());
+    }
+
+    /// Jump if the current cell is zero (`je`) or not zero (`jne`), returning the patch position.
+    fn test_cell(&mut self, jcc: u8) -> usize {
+        // cmp byte [r12 + r13], 0
+        self.emit(&[0x43, 0x80, 0x3C, 0x2C, 0x00]);
+        self.jump(&[0x0F, jcc])
+    }
+
+    /// Move the cursor `n` cells, leaving it where it was if that fails.
+    fn mv(&mut self, n: isize) {
+        self.address(n);
+        // mov r13, rax
+        self.emit(&[0x49, 0x89, 0xC5]);
+    }
+
+    fn block(&mut self, ir: &[(Ir, usize)]) {
+        for (i, (instr, at)) in ir.iter().enumerate() {
+            self.at = *at;
+            match instr {
+                Ir::Add(delta) => {
+                    // add byte [r12 + r13], delta
+                    self.emit(&[0x43, 0x80, 0x04, 0x2C, *delta as u8]);
+                }
+                Ir::Move(n) => self.mv(*n),
+                Ir::AddAt { offset, delta } => {
+                    self.address(*offset);
+                    // add byte [r12 + rax], delta
+                    self.emit(&[0x41, 0x80, 0x04, 0x04, *delta as u8]);
+                }
+                Ir::Check { low, high } => {
+                    self.address(*low);
+                    self.address(*high);
+                }
+                Ir::Output | Ir::Input | Ir::DebugDump => {
+                    // mov rsi, r13
+                    self.emit(&[0x4C, 0x89, 0xEE]);
+                    let f = match instr {
+                        Ir::Output => self.callbacks.output,
+                        Ir::Input => self.callbacks.input,
+                        _ => self.callbacks.dump,
+                    };
+                    self.call(f);
+                }
+                Ir::Loop(body) => self.repeat(|asm| asm.block(body)),
+                Ir::SetZero => {
+                    // mov byte [r12 + r13], 0
+                    self.emit(&[0x43, 0xC6, 0x04, 0x2C, 0x00]);
+                }
+                Ir::MulAdd { offset, factor } => {
+                    // The loop this came from would not have run at all
+                    let skip = self.test_cell(0x84);
+                    // Check the cells of the ones still to come too, so nothing
+                    // has changed yet if the loop would have left the tape
+                    for (next, _) in &ir[i..] {
+                        match next {
+                            Ir::MulAdd { offset, .. } => self.address(*offset),
+                            _ => break,
+                        }
+                    }
+                    self.address(*offset);
+                    // movzx ecx, byte [r12 + r13]
+                    self.emit(&[0x43, 0x0F, 0xB6, 0x0C, 0x2C]);
+                    // imul ecx, ecx, factor
+                    self.emit(&[0x69, 0xC9]);
+                    self.emit(&factor.to_le_bytes());
+                    // add byte [r12 + rax], cl
+                    self.emit(&[0x41, 0x00, 0x0C, 0x04]);
+                    let end = self.code.len();
+                    self.patch(skip, end);
+                }
+                Ir::ScanRight => self.repeat(|asm| asm.mv(1)),
+                Ir::ScanLeft => self.repeat(|asm| asm.mv(-1)),
+            }
+        }
+    }
+
+    /// Emit `body` as a loop that runs while the current cell is not zero.
+    fn repeat(&mut self, body: impl FnOnce(&mut Self)) {
+        let skip = self.test_cell(0x84);
+        let start = self.code.len();
+        body(self);
+        let back = self.test_cell(0x85);
+        self.patch(back, start);
+        let end = self.code.len();
+        self.patch(skip, end);
+    }
+}
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 2;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+extern "C" {
+    fn mmap(addr: *mut u8, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut u8;
+    fn mprotect(addr: *mut u8, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut u8, len: usize) -> i32;
+}
+
+/// A read-only, executable copy of some machine code.
+struct ExecutableBuffer {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl ExecutableBuffer {
+    fn new(code: &[u8]) -> Result<Self, BfError> {
+        let len = code.len();
+        // Safety: a fresh anonymous mapping is only written within its length,
+        // and is never writable and executable at the same time.
+        unsafe {
+            let ptr = mmap(
+                std::ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if ptr as isize == -1 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            let buffer = Self { ptr, len };
+
+            std::ptr::copy_nonoverlapping(code.as_ptr(), ptr, len);
+            if mprotect(ptr, len, PROT_READ | PROT_EXEC) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            Ok(buffer)
+        }
+    }
+}
+
+impl Drop for ExecutableBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.ptr, self.len);
+        }
+    }
+}
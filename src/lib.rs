@@ -0,0 +1,44 @@
+//! A brainfuck interpreter.
+//!
+//! ```
+//! let ops = brainfuck::parse("+++[>++<-]".as_bytes())?;
+//! let mut program = brainfuck::Program::new(ops);
+//! program.run()?;
+//! assert_eq!(program.memory().data()[1], 6);
+//! # Ok::<(), brainfuck::BfError>(())
+//! ```
+pub mod c;
+mod cell;
+pub mod debugger;
+mod diagnostic;
+mod error;
+mod format;
+pub mod ir;
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+mod jit;
+mod limits;
+mod lint;
+mod minify;
+mod operation;
+mod options;
+mod parse;
+pub mod profile;
+mod program;
+pub mod repl;
+mod source;
+mod tape;
+pub mod wat;
+
+pub use cell::Cell;
+pub use diagnostic::{diagnose, Diagnostic, Level};
+pub use error::BfError;
+pub use format::format;
+pub use limits::{Limit, Limits};
+pub use lint::{lint, LINTS};
+pub use minify::minify;
+pub use operation::Operation;
+pub use options::{EofPolicy, InputMode, OutputMode, TapePolicy};
+pub use parse::{parse, parse_mapped, parse_with, ParseOptions};
+pub use program::Program;
+pub use source::{SourceMap, Span};
+pub use tape::Tape;
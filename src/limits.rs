@@ -0,0 +1,35 @@
+use std::fmt;
+use std::time::Instant;
+
+/// Caps on the resources a program may use, `None` means unlimited.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Limits {
+    /// Instructions executed. `run_ir` counts each folded instruction and loop iteration once.
+    pub steps: Option<u64>,
+    /// Cells the tape may grow to cover, whether or not they are paged.
+    pub cells: Option<usize>,
+    /// Bytes written by `.`.
+    pub output: Option<u64>,
+    /// When to stop, checked every few hundred instructions.
+    pub deadline: Option<Instant>,
+}
+
+/// One of the `Limits`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Limit {
+    Steps,
+    Cells,
+    Output,
+    Deadline,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Limit::Steps => "step",
+            Limit::Cells => "memory",
+            Limit::Output => "output",
+            Limit::Deadline => "time",
+        })
+    }
+}
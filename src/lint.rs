@@ -0,0 +1,281 @@
+use crate::parse::tokens;
+use crate::{parse_with, BfError, Diagnostic, Level, Operation, ParseOptions, Span};
+
+/// Names of the lints `lint` checks for, with what each one means.
+pub const LINTS: &[(&str, &str)] = &[
+    (
+        "cancelling_pair",
+        "`+-`, `-+`, `<>` or `><`, which do nothing",
+    ),
+    (
+        "start_loop",
+        "a loop before any cell is changed, which never runs",
+    ),
+    (
+        "dead_loop",
+        "a loop straight after another, which never runs",
+    ),
+    (
+        "infinite_loop",
+        "`[]`, which never ends if the cell isn't zero",
+    ),
+    (
+        "unbalanced_loop",
+        "a loop that moves the pointer each time round",
+    ),
+];
+
+/// Look for common mistakes and dead code in `source`, returning each one
+/// found with the name of its lint.
+///
+/// Lints named in `allow` aren't reported, and neither is any lint named in
+/// an `allow(<name>)` comment on the line it would be reported on. Loops that
+/// only move the pointer, like `[>]`, are taken to be scans and not reported
+/// as unbalanced.
+///
+/// Fails if the brackets are unbalanced, `diagnose` explains those.
+pub fn lint(
+    source: &[u8],
+    options: ParseOptions,
+    allow: &[&str],
+) -> Result<Vec<(&'static str, Diagnostic)>, BfError> {
+    parse_with(source, options)?;
+    let tokens: Vec<_> = tokens(source, options).filter_map(Result::ok).collect();
+    let op = |i: usize| tokens.get(i).map(|(op, _)| *op);
+    // Index of the `]` matching the `[` at `start`
+    let partner = |start: usize| {
+        let mut depth = 0;
+        let end = tokens[start..].iter().position(|(op, _)| {
+            match op {
+                Operation::JumpForward => depth += 1,
+                Operation::JumpBack => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        });
+        start + end.expect("brackets are balanced")
+    };
+
+    let mut found = Vec::new();
+    let mut warn =
+        |name, span: Span, message: &str, label: &str, related: Option<(Span, String)>| {
+            found.push((
+                name,
+                Diagnostic {
+                    level: Level::Warning,
+                    message: format!("{} ({})", message, name),
+                    span,
+                    label: label.to_string(),
+                    related,
+                },
+            ))
+        };
+
+    // Whether a cell might have been changed yet
+    let mut changed = false;
+    // For each open loop, the `[` and how far its body has moved the pointer so
+    // far, `None` once that depends on how often a nested loop ran. And whether
+    // the body has only moved the pointer.
+    let mut loops: Vec<(usize, Option<isize>, bool)> = Vec::new();
+    // Whether the last instruction was the second of a cancelling pair
+    let mut paired = false;
+    // The `]` of a loop that never runs, nothing up to it is reported
+    let mut skip_to = None;
+    for (i, &(current, span)) in tokens.iter().enumerate() {
+        if skip_to.is_some_and(|end| i <= end) {
+            continue;
+        }
+        if let Some((_, _, only_moves)) = loops.last_mut() {
+            *only_moves &= matches!(
+                current,
+                Operation::MoveLeft | Operation::MoveRight | Operation::JumpBack
+            );
+        }
+        match current {
+            Operation::Increment | Operation::Decrement | Operation::Input => changed = true,
+            _ => {}
+        }
+        match current {
+            Operation::MoveRight | Operation::MoveLeft => {
+                let step = if current == Operation::MoveRight {
+                    1
+                } else {
+                    -1
+                };
+                if let Some((_, Some(moved), _)) = loops.last_mut() {
+                    *moved += step;
+                }
+            }
+            Operation::JumpForward => {
+                let after_loop = i > 0 && op(i - 1) == Some(Operation::JumpBack);
+                if !changed {
+                    warn(
+                        "start_loop",
+                        span,
+                        "this loop never runs",
+                        "no cell has been changed yet, so this one is zero",
+                        None,
+                    );
+                    skip_to = Some(partner(i));
+                    continue;
+                } else if after_loop {
+                    warn(
+                        "dead_loop",
+                        span,
+                        "this loop never runs",
+                        "the cell is always zero here",
+                        Some((
+                            tokens[i - 1].1,
+                            "the loop before only ends on a zero cell".to_string(),
+                        )),
+                    );
+                } else if op(i + 1) == Some(Operation::JumpBack) {
+                    warn(
+                        "infinite_loop",
+                        span,
+                        "this loop never ends if the cell isn't zero",
+                        "nothing in it changes the cell",
+                        None,
+                    );
+                }
+                loops.push((i, Some(0), true));
+            }
+            Operation::JumpBack => {
+                let (start, moved, only_moves) = loops.pop().expect("brackets are balanced");
+                match moved {
+                    Some(moved) if moved != 0 && !only_moves => {
+                        let direction = if moved > 0 { "right" } else { "left" };
+                        warn(
+                            "unbalanced_loop",
+                            tokens[start].1,
+                            "this loop moves the pointer each time round",
+                            &format!(
+                                "{} cells {} by the end of each pass",
+                                moved.abs(),
+                                direction
+                            ),
+                            Some((span, "the pass ends here".to_string())),
+                        );
+                    }
+                    _ => {}
+                }
+                // A nested loop that moves the pointer makes the outer one's movement unknown
+                if let Some((_, outer, _)) = loops.last_mut() {
+                    if moved != Some(0) {
+                        *outer = None;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        let inverse = match current {
+            Operation::Increment => Some(Operation::Decrement),
+            Operation::Decrement => Some(Operation::Increment),
+            Operation::MoveRight => Some(Operation::MoveLeft),
+            Operation::MoveLeft => Some(Operation::MoveRight),
+            _ => None,
+        };
+        // The second of a pair can't start another one
+        paired = !paired && inverse.is_some() && op(i + 1) == inverse;
+        if paired {
+            warn(
+                "cancelling_pair",
+                span,
+                "these instructions cancel each other out",
+                "this does nothing",
+                Some((tokens[i + 1].1, "together with this".to_string())),
+            );
+        }
+    }
+
+    // Allowed lints are dropped afterwards so the loop above doesn't need to know about them
+    let text = String::from_utf8_lossy(source);
+    let lines: Vec<_> = text.lines().collect();
+    let allowed = |name: &str, span: Span| {
+        allow.contains(&name)
+            || lines
+                .get(span.line - 1)
+                .is_some_and(|line| line.contains(&format!("allow({})", name)))
+    };
+    Ok(found
+        .into_iter()
+        .filter(|(name, diagnostic)| !allowed(name, diagnostic.span))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The lints reported for `source` and where.
+    fn lints(source: &str, allow: &[&str]) -> Vec<String> {
+        lint(source.as_bytes(), ParseOptions::default(), allow)
+            .unwrap()
+            .into_iter()
+            .map(|(name, d)| format!("{} {}", name, d.span))
+            .collect()
+    }
+
+    #[test]
+    fn lint_clean() {
+        let rot13 = include_str!("../tests/programs/rot13.bf");
+        assert!(lints("++[>+<-]>[>]<.", &[]).is_empty());
+        // Its special cases step off the end of the loops on purpose
+        assert_eq!(
+            lints(rot13, &[]),
+            [
+                "unbalanced_loop 7:14",
+                "unbalanced_loop 14:15",
+                "unbalanced_loop 15:14"
+            ]
+        );
+        assert!(lints(rot13, &["unbalanced_loop"]).is_empty());
+    }
+
+    #[test]
+    fn lint_loops() {
+        assert_eq!(lints(">[-]+", &[]), ["start_loop 1:2"]);
+        // Once, for the outermost loop, and nothing inside it
+        assert_eq!(lints("[[-][+-]]+[>+<-]", &[]), ["start_loop 1:1"]);
+        assert_eq!(lints("+[-][.]", &[]), ["dead_loop 1:5"]);
+        assert_eq!(lints("+[]", &[]), ["infinite_loop 1:2"]);
+        assert_eq!(lints("+[>+]", &[]), ["unbalanced_loop 1:2"]);
+        // Only the innermost loop with a known movement is reported
+        assert_eq!(lints("+[[>+]<<]", &[]), ["unbalanced_loop 1:3"]);
+    }
+
+    #[test]
+    fn lint_cancelling_pairs() {
+        assert_eq!(
+            lints("+-+>\n<", &[]),
+            ["cancelling_pair 1:1", "cancelling_pair 1:4"]
+        );
+    }
+
+    #[test]
+    fn lint_allow() {
+        assert!(lints("+-", &["cancelling_pair"]).is_empty());
+        assert_eq!(
+            lints("+- allow(cancelling_pair)\n+-", &[]),
+            ["cancelling_pair 2:1"]
+        );
+    }
+
+    #[test]
+    fn lint_render() {
+        let source = b"+[-][.]";
+        let lints = lint(source, ParseOptions::default(), &[]).unwrap();
+        assert_eq!(lints[0].0, "dead_loop");
+        assert_eq!(
+            lints[0].1.render("test.bf", source),
+            "warning: this loop never runs (dead_loop)
+ --> test.bf:1:5
+  |
+1 | +[-][.]
+  |    - the loop before only ends on a zero cell
+  |     ^ the cell is always zero here
+"
+        );
+    }
+}
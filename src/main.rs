@@ -1,319 +1,608 @@
-use std::io::prelude::*;
-
-#[derive(PartialEq, Debug)]
-enum Operation {
-    MoveRight,
-    MoveLeft,
-    Increment,
-    Decrement,
-    Output,
-    Input,
-    JumpForward,
-    JumpBack,
-    NoOp,
+use std::io::{Read, Stdout};
+use std::time::{Duration, Instant};
+
+use brainfuck::c::{self, COptions};
+use brainfuck::debugger::Debugger;
+use brainfuck::profile;
+use brainfuck::repl::Repl;
+use brainfuck::{
+    diagnose, format, ir, lint, minify, parse_mapped, wat, BfError, Cell, EofPolicy, InputMode,
+    Limits, OutputMode, ParseOptions, Program, SourceMap, TapePolicy, LINTS,
+};
+
+const USAGE: &str = "usage: brainfuck [command] [options] <file | - | -e <code>>";
+
+const HELP: &str = "usage: brainfuck [command] [options] <file | - | -e <code>>
+
+commands:
+    run          run the program, the default
+    check        check the program's brackets without running it
+    lint         warn about likely mistakes and dead code, see lints below
+    fmt          print the program with one line per loop, indented by nesting
+    minify       print the shortest equivalent program, without comments
+    compile      translate the program to another language, see --target
+    debug        step through the program with commands read from stdin
+    profile      run the program, then report how often each line and instruction ran
+    repl         run lines of brainfuck read from stdin on one memory, takes no program
+    help         show this message
+
+program:
+    <file>       read the program from a file
+    -            read the program from stdin
+    -e <code>    use <code> as the program
+
+options:
+    --debug-hash                      treat `#` as an instruction that dumps memory to stderr
+    --cell-size <8|16|32>             bits in a cell, 8 by default
+    --signed                          make cells signed, not for compile
+    --eof <unchanged|0|-1>            what `,` does at the end of input, unchanged by default
+    --input-mode <byte|decimal>       read a byte, or a line with a number, for each `,`
+    --output-mode <raw|decimal|hex|latin1>
+                                      how `.` writes a cell, a raw byte by default
+    --tape <grow-right|grow-both|wrap:<cells>|fixed:<cells>|classic>
+                                      what happens at the edges of memory, grow-right by default
+    --paged                           only allocate memory for the parts of the tape written to
+    --input <file>                    read `,` from a file, by default stdin for run and profile
+                                      and nothing for debug and repl
+    --max-steps <n>                   stop after executing n instructions
+    --max-cells <n>                   stop when memory grows past n cells
+    --max-output <bytes>              stop before writing more than this much output
+    --timeout <seconds>               stop after running for this long
+    --jit                             compile to native code before running, run only
+    --target <c|wat>                  what compile produces, c by default; wat has 8-bit
+                                      cells and takes no cell, tape or I/O options
+    --tape-size <cells>               cells the C program allocates up front
+    --allow <lint>                    don't report a lint, or put `allow(<lint>)` in a comment
+                                      on the line it is reported on
+    --cell-type <c type>              unsigned C type of the C program's cells,
+                                      by default uint8_t, uint16_t or uint32_t for --cell-size
+    -h, --help                        show this message
+
+exit status:
+    0    success
+    1    the program's brackets are unbalanced, or lint found something
+    2    the command line is invalid
+    3    the program failed while running, by moving off the tape, going over a limit
+         or reading invalid input
+    4    reading or writing a file or stream failed";
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Command {
+    Run,
+    Check,
+    Lint,
+    Fmt,
+    Minify,
+    Compile,
+    Debug,
+    Profile,
+    Repl,
 }
 
-impl Default for Operation {
-    fn default() -> Self {
-        Operation::NoOp
-    }
+/// Where the program comes from.
+enum Source {
+    File(String),
+    Stdin,
+    /// Given on the command line with `-e`
+    Inline(String),
 }
 
-impl From<char> for Operation {
-    fn from(c: char) -> Self {
-        match c {
-            '>' => Operation::MoveRight,
-            '<' => Operation::MoveLeft,
-            '+' => Operation::Increment,
-            '-' => Operation::Decrement,
-            '.' => Operation::Output,
-            ',' => Operation::Input,
-            '[' => Operation::JumpForward,
-            ']' => Operation::JumpBack,
-            _ => Operation::NoOp,
+impl Source {
+    /// Name for the program in messages.
+    fn name(&self) -> &str {
+        match self {
+            Source::File(path) => path,
+            Source::Stdin => "<stdin>",
+            Source::Inline(_) => "<-e>",
         }
     }
-}
 
-impl From<u8> for Operation {
-    fn from(n: u8) -> Self {
-        Self::from(char::from(n))
+    fn read(&self) -> std::io::Result<Vec<u8>> {
+        match self {
+            Source::File(path) => std::fs::read(path),
+            Source::Stdin => {
+                let mut source = Vec::new();
+                std::io::stdin().read_to_end(&mut source)?;
+                Ok(source)
+            }
+            Source::Inline(code) => Ok(code.clone().into_bytes()),
+        }
     }
 }
 
-struct Tape<T: Default> {
-    cursor: usize,
-    data: Vec<T>,
+/// Settings for running a program.
+struct RunOptions {
+    jit: bool,
+    cell_size: u32,
+    signed: bool,
+    eof: EofPolicy,
+    input_mode: InputMode,
+    output_mode: OutputMode,
+    tape: TapePolicy,
+    paged: bool,
+    limits: Limits,
+    /// How long the program may run for, turned into a deadline once it starts
+    timeout: Option<Duration>,
+    /// File for `,` to read from
+    input: Option<String>,
 }
 
-impl<T: Default> Tape<T> {
-    fn new(data: Vec<T>) -> Self {
-        Self { data, cursor: 0 }
-    }
-
-    fn mv_right(&mut self) {
-        self.cursor += 1;
-        if self.cursor >= self.data.len() {
-            self.data.resize_with(self.data.len() * 2, T::default);
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            jit: false,
+            cell_size: 8,
+            signed: false,
+            eof: EofPolicy::default(),
+            input_mode: InputMode::default(),
+            output_mode: OutputMode::default(),
+            tape: TapePolicy::default(),
+            paged: false,
+            limits: Limits::default(),
+            timeout: None,
+            input: None,
         }
     }
+}
 
-    fn mv_left(&mut self) {
-        self.cursor -= 1;
-    }
-
-    fn cell(&self) -> &T {
-        &self.data[self.cursor]
-    }
+/// Apply `options` to a program with byte cells.
+fn configure<R: Read>(program: Program<R, Stdout>, options: &RunOptions) -> Program<R, Stdout> {
+    let mut program = program
+        .with_eof(options.eof)
+        .with_input_mode(options.input_mode)
+        .with_output_mode(options.output_mode)
+        .with_tape_policy(options.tape);
+    if options.paged {
+        program = program.with_paged_memory();
+    }
+    program = program.with_limits(Limits {
+        deadline: options.timeout.map(|timeout| Instant::now() + timeout),
+        ..options.limits
+    });
+    program
+}
 
-    fn cell_mut(&mut self) -> &mut T {
-        &mut self.data[self.cursor]
+/// Where `,` reads from, `--input` if it was given and otherwise stdin, or
+/// nothing when stdin is taken by commands.
+///
+/// Exits with 4 if the input file can't be opened, naming it rather than the program.
+fn input(options: &RunOptions, stdin: bool) -> Box<dyn Read> {
+    match &options.input {
+        Some(path) => match std::fs::File::open(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("error: {}: {}", path, e);
+                std::process::exit(4);
+            }
+        },
+        None if stdin => Box::new(std::io::stdin()),
+        None => Box::new(std::io::empty()),
     }
 }
 
-struct Program {
-    ops: Tape<Operation>,
-    memory: Tape<u8>,
+/// Evaluate `$body` with `$program`, a program with byte cells, switched to
+/// the cells `$options` ask for.
+macro_rules! with_cells {
+    ($options:expr, $program:ident => $body:expr) => {
+        match ($options.cell_size, $options.signed) {
+            (8, false) => $body,
+            (8, true) => {
+                let $program = $program.with_cell::<i8>();
+                $body
+            }
+            (16, false) => {
+                let $program = $program.with_cell::<u16>();
+                $body
+            }
+            (16, true) => {
+                let $program = $program.with_cell::<i16>();
+                $body
+            }
+            (32, false) => {
+                let $program = $program.with_cell::<u32>();
+                $body
+            }
+            _ => {
+                let $program = $program.with_cell::<i32>();
+                $body
+            }
+        }
+    };
 }
 
-impl Program {
-    fn new(program: Vec<Operation>) -> Self {
-        // Allocate some memory to start with
-        let mut memory = Vec::new();
-        memory.resize(512, 0);
+fn run(
+    source: &[u8],
+    parse: ParseOptions,
+    options: &RunOptions,
+    map: &mut SourceMap,
+) -> Result<(), BfError> {
+    let ops = parse_mapped(source, parse, map)?;
+    let input = input(options, true);
+    let program = configure(Program::with_io(ops, input, std::io::stdout()), options);
 
-        Self {
-            ops: Tape::new(program),
-            memory: Tape::new(memory),
-        }
-    }
-
-    /// bf increment `+`
-    fn inc(&mut self) {
-        *self.memory.cell_mut() = self.memory.cell().wrapping_add(1)
+    match (options.cell_size, options.signed) {
+        (8, false) => run_bytes(program, options),
+        _ => with_cells!(options, program => interpret(program, options)),
     }
+}
 
-    /// bf decrement `-`
-    fn dec(&mut self) {
-        *self.memory.cell_mut() = self.memory.cell().wrapping_sub(1)
+/// Run a program with byte cells, which is the only kind the JIT supports.
+fn run_bytes<R: Read>(
+    mut program: Program<R, Stdout>,
+    options: &RunOptions,
+) -> Result<(), BfError> {
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    if options.jit {
+        return program.run_jit();
     }
-
-    /// bf move left `<`
-    fn mvl(&mut self) {
-        self.memory.mv_left()
+    #[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+    if options.jit {
+        eprintln!("warning: --jit is not supported on this platform, interpreting instead");
     }
 
-    /// bf move right `>`
-    fn mvr(&mut self) {
-        self.memory.mv_right()
-    }
+    program.run()
+}
 
-    /// bf jump backward `]`
-    fn jpb(&mut self) {
-        if *self.memory.cell() != 0 {
-            let mut count = 1;
-            while count > 0 {
-                self.ops.mv_left();
-
-                if *self.ops.cell() == Operation::JumpBack {
-                    count += 1;
-                } else if *self.ops.cell() == Operation::JumpForward {
-                    count -= 1;
-                }
-            }
-        }
+fn interpret<R: Read, C: Cell>(
+    mut program: Program<R, Stdout, C>,
+    options: &RunOptions,
+) -> Result<(), BfError> {
+    if options.jit {
+        eprintln!("warning: --jit only supports unsigned 8-bit cells, interpreting instead");
     }
+    program.run()
+}
 
-    /// bf jump foward `[`
-    fn jpf(&mut self) {
-        if *self.memory.cell() == 0 {
-            let mut count = 1;
-            while count > 0 {
-                self.ops.mv_right();
-
-                if *self.ops.cell() == Operation::JumpForward {
-                    count += 1;
-                } else if *self.ops.cell() == Operation::JumpBack {
-                    count -= 1;
-                }
-            }
-        }
-    }
+/// Only check that the program parses.
+fn check(source: &[u8], parse: ParseOptions, map: &mut SourceMap) -> Result<(), BfError> {
+    let ops = parse_mapped(source, parse, map)?;
+    println!("{}: {} instructions", map.file(), ops.len());
+    Ok(())
+}
 
-    /// bf output `.`
-    fn prt(&self) {
-        print!("{}", char::from(*self.memory.cell()));
-    }
+/// Report lints to stderr, exiting with 1 if there are any.
+fn lint_program(
+    source: &[u8],
+    parse: ParseOptions,
+    allow: &[String],
+    map: &mut SourceMap,
+) -> Result<(), BfError> {
+    parse_mapped(source, parse, map)?;
+    let allow: Vec<_> = allow.iter().map(String::as_str).collect();
+    let lints = lint(source, parse, &allow)?;
+    let rendered: Vec<_> = lints
+        .iter()
+        .map(|(_, l)| l.render(map.file(), source))
+        .collect();
+    if !rendered.is_empty() {
+        eprint!("{}", rendered.join("\n"));
+        let plural = if rendered.len() == 1 { "" } else { "s" };
+        eprintln!("\n{}: {} warning{}", map.file(), rendered.len(), plural);
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
-    /// bf input `,`
-    fn inp(&mut self) {
-        let mut buff = String::new();
-        std::io::stdin().read_line(&mut buff).unwrap();
-        *self.memory.cell_mut() = buff.trim().parse().unwrap();
-    }
+/// Print the program laid out by `format`.
+fn fmt(source: &[u8], parse: ParseOptions, map: &mut SourceMap) -> Result<(), BfError> {
+    // Parsed for the map, so errors can say where they are
+    parse_mapped(source, parse, map)?;
+    print!("{}", format(source, parse)?);
+    Ok(())
+}
 
-    /// Execute the current operation. Should not be used directly, use `step` instead.
-    fn operate(&mut self) {
-        match *self.ops.cell() {
-            Operation::Increment => self.inc(),
-            Operation::Decrement => self.dec(),
-            Operation::MoveLeft => self.mvl(),
-            Operation::MoveRight => self.mvr(),
-            Operation::Output => self.prt(),
-            Operation::Input => self.inp(),
-            Operation::JumpForward => self.jpf(),
-            Operation::JumpBack => self.jpb(),
-            _ => {}
-        }
-    }
+/// Print the program as `minify` shrinks it.
+fn minified(source: &[u8], parse: ParseOptions, map: &mut SourceMap) -> Result<(), BfError> {
+    let ops = parse_mapped(source, parse, map)?;
+    let code: String = minify(&ops).iter().map(ToString::to_string).collect();
+    print!("{}", code);
+    Ok(())
+}
 
-    /// Execute the next operation
-    fn step(&mut self) {
-        self.operate();
-        self.ops.mv_right();
-    }
+/// Run a program, then report on where it spent its steps to stderr.
+fn profile(
+    source: &[u8],
+    parse: ParseOptions,
+    options: &RunOptions,
+    map: &mut SourceMap,
+) -> Result<(), BfError> {
+    let ops = parse_mapped(source, parse, map)?;
+    let input = input(options, true);
+    let program = configure(Program::with_io(ops, input, std::io::stdout()), options);
+
+    with_cells!(options, program => profile_cells(program, source, map))
+}
 
-    /// Execute all operations
-    fn run(&mut self) {
-        while *self.ops.cell() != Operation::NoOp {
-            self.step();
-        }
-    }
+fn profile_cells<R: Read, C: Cell>(
+    mut program: Program<R, Stdout, C>,
+    source: &[u8],
+    map: &SourceMap,
+) -> Result<(), BfError> {
+    let mut counts = Vec::new();
+    let result = program.run_profiled(&mut counts);
+    // Report on however far it got, even if it failed
+    profile::report(&mut std::io::stderr().lock(), source, map, &counts, 10)?;
+    result
 }
 
-fn parse<T: Read>(stream: T) -> impl Iterator<Item = Operation> {
-    std::io::BufReader::new(stream)
-        .bytes()
-        // Get valid bytes
-        .filter_map(|b| b.ok())
-        // Convert to operations
-        .map(|c| Operation::from(c))
-        // Ignore NoOps
-        .filter(|op| op != &Operation::NoOp)
+/// Step through a program under the control of commands on stdin.
+fn debug(
+    source: &[u8],
+    parse: ParseOptions,
+    options: &RunOptions,
+    map: &mut SourceMap,
+) -> Result<(), BfError> {
+    let ops = parse_mapped(source, parse, map)?;
+    let input = input(options, false);
+    let program = configure(Program::with_io(ops, input, std::io::stdout()), options);
+
+    with_cells!(options, program => debug_cells(program, source, map))
 }
 
-fn main() {
-    let input = std::env::args().nth(1).expect("Expected a file.");
-    let ops = parse(std::fs::File::open(input).expect("Invalid file path."));
-    let mut program = Program::new(ops.collect());
-    program.run();
+fn debug_cells<R: Read, C: Cell>(
+    mut program: Program<R, Stdout, C>,
+    source: &[u8],
+    map: &SourceMap,
+) -> Result<(), BfError> {
+    Debugger::new(&mut program, source, map).run(std::io::stdin().lock(), std::io::stdout())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Run lines of brainfuck from stdin on one memory.
+fn repl(parse: ParseOptions, options: &RunOptions) -> Result<(), BfError> {
+    let input = input(options, false);
+    let program = configure(
+        Program::with_io(Vec::new(), input, std::io::stdout()),
+        options,
+    );
 
-    #[test]
-    fn tape_move_right() {
-        let mut tape = Tape::new(vec![0, 0]);
-        tape.mv_right();
-        assert_eq!(tape.cursor, 1);
-    }
+    with_cells!(options, program => repl_cells(program, parse))
+}
 
-    #[test]
-    fn tape_move_left() {
-        let mut tape = Tape::new(vec![0, 0]);
-        tape.mv_right();
-        tape.mv_left();
-        assert_eq!(tape.cursor, 0);
-    }
+fn repl_cells<R: Read, C: Cell>(
+    mut program: Program<R, Stdout, C>,
+    parse: ParseOptions,
+) -> Result<(), BfError> {
+    Repl::new(&mut program, parse).run(std::io::stdin().lock(), std::io::stdout())
+}
 
-    #[test]
-    fn tape_cell() {
-        let mut tape = Tape::new(vec![0, 0]);
-        tape.mv_right();
-        assert_eq!(*tape.cell(), 0);
-    }
+fn compile(
+    source: &[u8],
+    parse: ParseOptions,
+    options: &COptions,
+    map: &mut SourceMap,
+) -> Result<(), BfError> {
+    let ops = parse_mapped(source, parse, map)?;
+    let ir = ir::optimize(ir::lower(&ops)?);
+    print!("{}", c::compile(&ir, options));
+    Ok(())
+}
 
-    #[test]
-    fn prog_inc() {
-        let ops = vec![Operation::Increment];
-        let mut prog = Program::new(ops);
-        prog.run();
-        assert_eq!(*prog.memory.cell(), 1);
-    }
+fn compile_wat(source: &[u8], parse: ParseOptions, map: &mut SourceMap) -> Result<(), BfError> {
+    let ops = parse_mapped(source, parse, map)?;
+    print!("{}", wat::compile(&ops)?);
+    Ok(())
+}
 
-    #[test]
-    fn prog_dec() {
-        let ops = vec![Operation::Increment, Operation::Decrement];
-        let mut prog = Program::new(ops);
-        prog.run();
-        assert_eq!(*prog.memory.cell(), 0);
+/// Exit status for an error, as listed in `HELP`.
+fn status(e: &BfError) -> i32 {
+    match e {
+        BfError::UnmatchedOpen(_) | BfError::UnmatchedClose(_) => 1,
+        BfError::IoError(_) => 4,
+        _ => 3,
     }
+}
 
-    #[test]
-    fn prog_inc_wrapping() {
-        let ops = vec![Operation::Increment];
-        let mut prog = Program::new(ops);
-        *prog.memory.cell_mut() = 255;
-        prog.run();
-        assert_eq!(*prog.memory.cell(), 0);
+/// Write `e` to stderr, pointing at where in `source` it happened if that is known.
+fn report(e: &BfError, source: &[u8], parse: ParseOptions, map: &SourceMap) {
+    if let BfError::UnmatchedOpen(_) | BfError::UnmatchedClose(_) = e {
+        let rendered: Vec<_> = diagnose(source, parse)
+            .iter()
+            .map(|d| d.render(map.file(), source))
+            .collect();
+        if !rendered.is_empty() {
+            eprint!("{}", rendered.join("\n"));
+            return;
+        }
     }
+    // Point at the operation the error is about when it's known
+    let location = e.instruction().and_then(|ip| map.locate(ip));
+    eprintln!(
+        "error: {}: {}",
+        location.as_deref().unwrap_or(map.file()),
+        e
+    );
+}
 
-    #[test]
-    fn prog_dec_wrapping() {
-        let ops = vec![Operation::Decrement];
-        let mut prog = Program::new(ops);
-        prog.run();
-        assert_eq!(*prog.memory.cell(), 255);
-    }
+fn usage() -> ! {
+    eprintln!("{}\ntry `brainfuck --help` for more information", USAGE);
+    std::process::exit(2);
+}
 
-    #[test]
-    fn prog_step() {
-        let ops = vec![Operation::Decrement, Operation::Increment];
-        let mut prog = Program::new(ops);
-        prog.step();
-        assert_eq!(*prog.memory.cell(), 255);
-        prog.step();
-        assert_eq!(*prog.memory.cell(), 0);
+fn help() -> ! {
+    println!("{}", HELP);
+    println!("\nlints:");
+    for (name, description) in LINTS {
+        println!("    {:<18}{}", name, description);
     }
+    std::process::exit(0);
+}
 
-    #[test]
-    fn prog_jmp() {
-        let ops = vec![
-            Operation::Increment,
-            Operation::JumpForward,
-            Operation::JumpBack,
-        ];
-        let mut prog = Program::new(ops);
-        prog.step();
-        assert_eq!(*prog.ops.cell(), Operation::JumpForward);
-        prog.step();
-        assert_eq!(*prog.ops.cell(), Operation::JumpBack);
-        prog.step();
-        assert_eq!(*prog.ops.cell(), Operation::JumpBack);
+/// Parse the value of an option, exiting with usage if it is missing or invalid.
+fn value<T>(arg: Option<String>) -> T
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match arg.map(|arg| arg.parse()) {
+        Some(Ok(value)) => value,
+        Some(Err(e)) => {
+            eprintln!("error: {}", e);
+            usage()
+        }
+        None => usage(),
     }
+}
 
-    #[test]
-    fn prog_jmp_nested() {
-        let ops = vec![
-            Operation::Increment,
-            Operation::JumpForward,
-            Operation::JumpForward,
-            Operation::Decrement,
-            Operation::JumpBack,
-            Operation::JumpBack,
-        ];
-        let mut prog = Program::new(ops);
-        prog.run();
-        assert_eq!(*prog.memory.cell(), 0);
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some("run") => Some(Command::Run),
+        Some("check") => Some(Command::Check),
+        Some("lint") => Some(Command::Lint),
+        Some("fmt") => Some(Command::Fmt),
+        Some("minify") => Some(Command::Minify),
+        Some("compile") => Some(Command::Compile),
+        Some("debug") => Some(Command::Debug),
+        Some("profile") => Some(Command::Profile),
+        Some("repl") => Some(Command::Repl),
+        Some("help") => help(),
+        _ => None,
+    };
+    if command.is_some() {
+        args.next();
+    }
+    let command = command.unwrap_or(Command::Run);
+    let compiling = command == Command::Compile;
+
+    let mut run_options = RunOptions::default();
+    let mut target = None;
+    let mut options = COptions::default();
+    let mut cell_type = None;
+    // Options only the C target takes, so compiling to WAT doesn't quietly ignore them
+    let mut c_only = false;
+    let mut allow = Vec::new();
+    let mut parse = ParseOptions::default();
+    let mut source = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => help(),
+            "--debug-hash" => parse.debug_hash = true,
+            "--allow" if command == Command::Lint => match args.next() {
+                Some(name) if LINTS.iter().any(|(lint, _)| *lint == name) => allow.push(name),
+                Some(name) => {
+                    eprintln!("error: unknown lint `{}`", name);
+                    usage()
+                }
+                None => usage(),
+            },
+            "--jit" if command == Command::Run => run_options.jit = true,
+            "--input"
+                if !compiling
+                    && !matches!(
+                        command,
+                        Command::Check | Command::Lint | Command::Fmt | Command::Minify
+                    ) =>
+            {
+                run_options.input = Some(args.next().unwrap_or_else(|| usage()))
+            }
+            "--cell-size" => {
+                c_only = true;
+                run_options.cell_size = match args.next().as_deref() {
+                    Some("8") => 8,
+                    Some("16") => 16,
+                    Some("32") => 32,
+                    _ => usage(),
+                }
+            }
+            "--signed" if !compiling => run_options.signed = true,
+            "--tape" if !compiling => run_options.tape = value(args.next()),
+            "--paged" if !compiling => run_options.paged = true,
+            "--max-steps" if !compiling => run_options.limits.steps = Some(value(args.next())),
+            "--max-cells" if !compiling => match value(args.next()) {
+                0 => {
+                    eprintln!("error: --max-cells must be at least 1");
+                    usage()
+                }
+                cells => run_options.limits.cells = Some(cells),
+            },
+            "--max-output" if !compiling => run_options.limits.output = Some(value(args.next())),
+            "--timeout" if !compiling => {
+                let seconds: f64 = value(args.next());
+                match Duration::try_from_secs_f64(seconds) {
+                    Ok(timeout) => run_options.timeout = Some(timeout),
+                    Err(e) => {
+                        eprintln!("error: {}", e);
+                        usage()
+                    }
+                }
+            }
+            "--eof" => {
+                c_only = true;
+                let eof = value(args.next());
+                run_options.eof = eof;
+                options.eof = eof;
+            }
+            "--input-mode" => {
+                c_only = true;
+                let input_mode = value(args.next());
+                run_options.input_mode = input_mode;
+                options.input_mode = input_mode;
+            }
+            "--output-mode" => {
+                c_only = true;
+                let output_mode = value(args.next());
+                run_options.output_mode = output_mode;
+                options.output_mode = output_mode;
+            }
+            "--target" if compiling => target = args.next(),
+            "--tape-size" if compiling => {
+                c_only = true;
+                options.tape_size = value(args.next())
+            }
+            "--cell-type" if compiling => {
+                c_only = true;
+                cell_type = Some(args.next().unwrap_or_else(|| usage()))
+            }
+            "-e" if source.is_none() => {
+                source = Some(Source::Inline(args.next().unwrap_or_else(|| usage())))
+            }
+            "-" if source.is_none() => source = Some(Source::Stdin),
+            _ if arg.starts_with('-') => usage(),
+            _ if source.is_none() => source = Some(Source::File(arg)),
+            _ => usage(),
+        }
     }
+    options.cell_type = cell_type.unwrap_or_else(|| format!("uint{}_t", run_options.cell_size));
 
-    #[test]
-    fn prog_ops_extends() {
-        let ops = vec![Operation::Increment];
-        let mut prog = Program::new(ops);
-        prog.step();
-        prog.step();
-        assert_eq!(*prog.ops.cell(), Operation::NoOp);
+    if command == Command::Repl {
+        if source.is_some() {
+            usage();
+        }
+        if let Err(e) = repl(parse, &run_options) {
+            eprintln!("error: {}", e);
+            std::process::exit(status(&e));
+        }
+        return;
     }
 
-    #[test]
-    fn prog_mem_extends() {
-        let mut ops = vec![];
-        ops.resize_with(1000, || Operation::MoveRight);
-        let mut prog = Program::new(ops);
-        prog.run();
-        assert_eq!(prog.memory.cursor, 1000);
+    let source = source.unwrap_or_else(|| usage());
+    let code = match source.read() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}: {}", source.name(), e);
+            std::process::exit(4);
+        }
+    };
+
+    let mut map = SourceMap::new(source.name());
+    let result = match command {
+        Command::Run => run(&code, parse, &run_options, &mut map),
+        Command::Check => check(&code, parse, &mut map),
+        Command::Lint => lint_program(&code, parse, &allow, &mut map),
+        Command::Fmt => fmt(&code, parse, &mut map),
+        Command::Minify => minified(&code, parse, &mut map),
+        Command::Compile => match target.as_deref() {
+            Some("c") | None => compile(&code, parse, &options, &mut map),
+            Some("wat") if !c_only => compile_wat(&code, parse, &mut map),
+            _ => usage(),
+        },
+        Command::Debug => debug(&code, parse, &run_options, &mut map),
+        Command::Profile => profile(&code, parse, &run_options, &mut map),
+        Command::Repl => unreachable!("the repl takes no program"),
+    };
+
+    if let Err(e) = result {
+        report(&e, &code, parse, &map);
+        std::process::exit(status(&e));
     }
 }
@@ -0,0 +1,105 @@
+use crate::Operation;
+
+/// The shortest operations that do the same as `ops`.
+///
+/// Pairs that undo each other, like `+-` and `<>`, are dropped, as are loops
+/// that can never run: those straight after another loop, which only ends on
+/// a zero cell, and those at the start, where all of memory is zero. This
+/// assumes the pointer never leaves the tape, and that the program starts on
+/// fresh memory. Unbalanced brackets are left as they are.
+pub fn minify(ops: &[Operation]) -> Vec<Operation> {
+    let mut out: Vec<Operation> = Vec::with_capacity(ops.len());
+    let mut i = 0;
+    while i < ops.len() {
+        let op = ops[i];
+        match (out.last(), op) {
+            (Some(Operation::Increment), Operation::Decrement)
+            | (Some(Operation::Decrement), Operation::Increment)
+            | (Some(Operation::MoveRight), Operation::MoveLeft)
+            | (Some(Operation::MoveLeft), Operation::MoveRight) => {
+                out.pop();
+            }
+            // The cell is still zero, from the start of memory or the end of a loop
+            (None | Some(Operation::JumpBack), Operation::JumpForward) => match matching(ops, i) {
+                Some(end) => i = end,
+                None => out.push(op),
+            },
+            _ => out.push(op),
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Index of the `]` that closes the `[` at `start`.
+fn matching(ops: &[Operation], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, op) in ops.iter().enumerate().skip(start) {
+        match op {
+            Operation::JumpForward => depth += 1,
+            Operation::JumpBack => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, Program};
+
+    fn min(src: &str) -> String {
+        let ops = parse(src.as_bytes()).unwrap();
+        minify(&ops).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn minify_strips_comments() {
+        assert_eq!(min("+ add one\n> move . print"), "+>.");
+    }
+
+    #[test]
+    fn minify_cancels_pairs() {
+        assert_eq!(min("++-+>><<<>-"), "+");
+        assert_eq!(min("+>+<-+>-<"), "+");
+        assert_eq!(min("+[+-]"), "+[]");
+    }
+
+    #[test]
+    fn minify_drops_dead_loops() {
+        assert_eq!(min("[comment, with. symbols-]+"), "+");
+        assert_eq!(min("+-<>[.]+"), "+");
+        assert_eq!(min("+[-][>+<][.]>"), "+[-]>");
+        assert_eq!(min("+[-]+-[.]"), "+[-]");
+        assert_eq!(min("+[>[-][.]<-]"), "+[>[-]<-]");
+    }
+
+    /// Run `src` on `input`, returning its output.
+    fn output(src: &str, input: &str) -> Vec<u8> {
+        let ops = parse(src.as_bytes()).unwrap();
+        let mut program = Program::with_io(ops, input.as_bytes(), Vec::new());
+        program.run().unwrap();
+        program.into_output()
+    }
+
+    #[test]
+    fn minify_is_equivalent() {
+        let rot13 = include_str!("../tests/programs/rot13.bf");
+        let hello = "[ hello ]++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+        for (src, input) in [
+            (rot13, "Hello, World!\n"),
+            (hello, ""),
+            ("+>-<+-[-]>>+<<[-]+++.", ""),
+        ] {
+            let minified = min(src);
+            assert!(minified.len() <= src.len());
+            assert_eq!(output(&minified, input), output(src, input));
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+
+/// A single brainfuck instruction.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Operation {
+    MoveRight,
+    MoveLeft,
+    Increment,
+    Decrement,
+    Output,
+    Input,
+    JumpForward,
+    JumpBack,
+    /// `#`, dumps the memory around the pointer to stderr. Only parsed when
+    /// `ParseOptions::debug_hash` is set, so `#` stays a comment otherwise.
+    DebugDump,
+    #[default]
+    NoOp,
+}
+
+impl From<char> for Operation {
+    fn from(c: char) -> Self {
+        match c {
+            '>' => Operation::MoveRight,
+            '<' => Operation::MoveLeft,
+            '+' => Operation::Increment,
+            '-' => Operation::Decrement,
+            '.' => Operation::Output,
+            ',' => Operation::Input,
+            '[' => Operation::JumpForward,
+            ']' => Operation::JumpBack,
+            _ => Operation::NoOp,
+        }
+    }
+}
+
+impl From<u8> for Operation {
+    fn from(n: u8) -> Self {
+        Self::from(char::from(n))
+    }
+}
+
+/// The source character for the operation, nothing for `NoOp`.
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = match self {
+            Operation::MoveRight => ">",
+            Operation::MoveLeft => "<",
+            Operation::Increment => "+",
+            Operation::Decrement => "-",
+            Operation::Output => ".",
+            Operation::Input => ",",
+            Operation::JumpForward => "[",
+            Operation::JumpBack => "]",
+            Operation::DebugDump => "#",
+            Operation::NoOp => "",
+        };
+        f.write_str(c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn op_from_char() {
+        assert_eq!(Operation::from('>'), Operation::MoveRight);
+        assert_eq!(Operation::from(b']'), Operation::JumpBack);
+        assert_eq!(Operation::from('a'), Operation::NoOp);
+    }
+
+    #[test]
+    fn op_display() {
+        for c in "><+-.,[]".chars() {
+            assert_eq!(Operation::from(c).to_string(), c.to_string());
+        }
+        assert_eq!(Operation::DebugDump.to_string(), "#");
+        assert_eq!(Operation::NoOp.to_string(), "");
+    }
+}
@@ -0,0 +1,166 @@
+use std::str::FromStr;
+
+/// What `,` does to the current cell once input has run out.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum EofPolicy {
+    /// Leave the cell as it was.
+    #[default]
+    Unchanged,
+    /// Set the cell to 0.
+    Zero,
+    /// Set the cell to -1, which is 255 for bytes.
+    MinusOne,
+}
+
+impl FromStr for EofPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unchanged" => Ok(EofPolicy::Unchanged),
+            "zero" | "0" => Ok(EofPolicy::Zero),
+            "minus-one" | "-1" | "255" => Ok(EofPolicy::MinusOne),
+            _ => Err(format!(
+                "unknown EOF policy {:?}, expected unchanged, 0 or -1",
+                s
+            )),
+        }
+    }
+}
+
+/// How `,` turns input into a cell value.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum InputMode {
+    /// Read a single raw byte.
+    #[default]
+    Byte,
+    /// Read a whole line and parse it as a decimal number.
+    DecimalLine,
+}
+
+impl FromStr for InputMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "byte" => Ok(InputMode::Byte),
+            "decimal" => Ok(InputMode::DecimalLine),
+            _ => Err(format!(
+                "unknown input mode {:?}, expected byte or decimal",
+                s
+            )),
+        }
+    }
+}
+
+/// How `.` writes the current cell.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum OutputMode {
+    /// Write the low byte of the cell as is.
+    #[default]
+    Raw,
+    /// Write the value in decimal, one per line.
+    Decimal,
+    /// Write the value in hexadecimal, one per line.
+    Hex,
+    /// Treat the low byte as a Latin-1 character and write it as UTF-8.
+    Latin1,
+}
+
+impl FromStr for OutputMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(OutputMode::Raw),
+            "decimal" => Ok(OutputMode::Decimal),
+            "hex" => Ok(OutputMode::Hex),
+            "latin1" => Ok(OutputMode::Latin1),
+            _ => Err(format!(
+                "unknown output mode {:?}, expected raw, decimal, hex or latin1",
+                s
+            )),
+        }
+    }
+}
+
+/// What happens at the edges of the memory tape.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum TapePolicy {
+    /// Grow to the right as needed, moving left of the first cell is an error.
+    #[default]
+    GrowRight,
+    /// Grow in both directions as needed.
+    GrowBoth,
+    /// A fixed number of cells, moving off one end comes back on the other.
+    Wrap(usize),
+    /// A fixed number of cells, moving off either end is an error.
+    Fixed(usize),
+}
+
+impl TapePolicy {
+    /// The 30,000 cells of the original implementation.
+    pub const CLASSIC: TapePolicy = TapePolicy::Fixed(30_000);
+}
+
+impl FromStr for TapePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cells = |n: &str| {
+            n.parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("invalid tape size {:?}", n))
+        };
+        match s.split_once(':') {
+            None if s == "grow-right" => Ok(TapePolicy::GrowRight),
+            None if s == "grow-both" => Ok(TapePolicy::GrowBoth),
+            None if s == "classic" => Ok(TapePolicy::CLASSIC),
+            Some(("wrap", n)) => Ok(TapePolicy::Wrap(cells(n)?)),
+            Some(("fixed", n)) => Ok(TapePolicy::Fixed(cells(n)?)),
+            _ => Err(format!(
+                "unknown tape policy {:?}, expected grow-right, grow-both, wrap:<cells>, fixed:<cells> or classic",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eof_from_str() {
+        assert_eq!("unchanged".parse(), Ok(EofPolicy::Unchanged));
+        assert_eq!("0".parse(), Ok(EofPolicy::Zero));
+        assert_eq!("-1".parse(), Ok(EofPolicy::MinusOne));
+        assert_eq!("255".parse(), Ok(EofPolicy::MinusOne));
+        assert!("eof".parse::<EofPolicy>().is_err());
+    }
+
+    #[test]
+    fn input_mode_from_str() {
+        assert_eq!("byte".parse(), Ok(InputMode::Byte));
+        assert_eq!("decimal".parse(), Ok(InputMode::DecimalLine));
+        assert!("line".parse::<InputMode>().is_err());
+    }
+
+    #[test]
+    fn output_mode_from_str() {
+        assert_eq!("raw".parse(), Ok(OutputMode::Raw));
+        assert_eq!("hex".parse(), Ok(OutputMode::Hex));
+        assert!("utf8".parse::<OutputMode>().is_err());
+    }
+
+    #[test]
+    fn tape_policy_from_str() {
+        assert_eq!("grow-right".parse(), Ok(TapePolicy::GrowRight));
+        assert_eq!("grow-both".parse(), Ok(TapePolicy::GrowBoth));
+        assert_eq!("wrap:100".parse(), Ok(TapePolicy::Wrap(100)));
+        assert_eq!("classic".parse(), Ok(TapePolicy::Fixed(30_000)));
+        assert!("fixed:0".parse::<TapePolicy>().is_err());
+        assert!("wrap".parse::<TapePolicy>().is_err());
+    }
+}
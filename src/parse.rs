@@ -0,0 +1,129 @@
+use std::io::prelude::*;
+
+use crate::{BfError, Operation, SourceMap, Span};
+
+/// Extensions to the language that `parse_with` can accept.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// Treat `#` as `Operation::DebugDump` instead of a comment.
+    pub debug_hash: bool,
+}
+
+/// Read brainfuck source from `stream`, dropping everything that isn't an instruction.
+///
+/// Fails if the stream can't be read or the brackets are unbalanced.
+pub fn parse<T: Read>(stream: T) -> Result<Vec<Operation>, BfError> {
+    parse_with(stream, ParseOptions::default())
+}
+
+/// Like `parse`, with the extensions in `options`.
+pub fn parse_with<T: Read>(stream: T, options: ParseOptions) -> Result<Vec<Operation>, BfError> {
+    parse_mapped(stream, options, &mut SourceMap::default())
+}
+
+/// Like `parse_with`, also recording where each operation is in `map`.
+///
+/// The map is filled in as parsing goes, so it can still locate the
+/// operation an error is about.
+pub fn parse_mapped<T: Read>(
+    stream: T,
+    options: ParseOptions,
+    map: &mut SourceMap,
+) -> Result<Vec<Operation>, BfError> {
+    let mut ops = Vec::new();
+    // Indices of the `[`s still waiting for a `]`
+    let mut open = Vec::new();
+
+    for token in tokens(stream, options) {
+        let (op, span) = token?;
+        map.push(span);
+        match op {
+            Operation::JumpForward => open.push(ops.len()),
+            Operation::JumpBack => {
+                open.pop().ok_or(BfError::UnmatchedClose(ops.len()))?;
+            }
+            _ => {}
+        }
+        ops.push(op);
+    }
+
+    match open.pop() {
+        Some(i) => Err(BfError::UnmatchedOpen(i)),
+        None => Ok(ops),
+    }
+}
+
+/// The instructions in `stream` and where they are, without checking the brackets.
+pub(crate) fn tokens<T: Read>(
+    stream: T,
+    options: ParseOptions,
+) -> impl Iterator<Item = std::io::Result<(Operation, Span)>> {
+    let (mut line, mut column) = (1, 0);
+    let bytes = std::io::BufReader::new(stream).bytes().enumerate();
+    bytes.filter_map(move |(offset, byte)| {
+        let byte = match byte {
+            Ok(byte) => byte,
+            Err(e) => return Some(Err(e)),
+        };
+        // Count characters rather than bytes, UTF-8 continuation bytes don't start one
+        if byte & 0xC0 != 0x80 {
+            column += 1;
+        }
+        if byte == b'\n' {
+            line += 1;
+            column = 0;
+        }
+
+        let op = match byte {
+            b'#' if options.debug_hash => Operation::DebugDump,
+            byte => Operation::from(byte),
+        };
+        let span = Span {
+            offset,
+            line,
+            column,
+        };
+        (op != Operation::NoOp).then_some(Ok((op, span)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_skips_comments() {
+        let ops = parse("+ a [-]".as_bytes()).unwrap();
+        assert_eq!(
+            ops,
+            vec![
+                Operation::Increment,
+                Operation::JumpForward,
+                Operation::Decrement,
+                Operation::JumpBack,
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_debug_hash() {
+        assert_eq!(parse("#+".as_bytes()).unwrap(), vec![Operation::Increment]);
+        let options = ParseOptions { debug_hash: true };
+        assert_eq!(
+            parse_with("#+".as_bytes(), options).unwrap(),
+            vec![Operation::DebugDump, Operation::Increment]
+        );
+    }
+
+    #[test]
+    fn parse_unmatched_open() {
+        let err = parse("+[[-]".as_bytes()).unwrap_err();
+        assert!(matches!(err, BfError::UnmatchedOpen(1)));
+    }
+
+    #[test]
+    fn parse_unmatched_close() {
+        let err = parse("+[-]]".as_bytes()).unwrap_err();
+        assert!(matches!(err, BfError::UnmatchedClose(4)));
+    }
+}
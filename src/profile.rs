@@ -0,0 +1,93 @@
+//! Reports on how often each part of a program ran, from the counts
+//! collected by `Program::run_profiled`.
+use std::io::{self, Write};
+
+use crate::SourceMap;
+
+/// Write how many steps each line of `source` took, followed by the `top`
+/// most executed instructions.
+///
+/// `counts` and `map` are indexed by instruction, as filled in by
+/// `Program::run_profiled` and `parse_mapped`.
+pub fn report<O: Write>(
+    out: &mut O,
+    source: &[u8],
+    map: &SourceMap,
+    counts: &[u64],
+    top: usize,
+) -> io::Result<()> {
+    let text = String::from_utf8_lossy(source);
+    let lines: Vec<_> = text.lines().collect();
+    let mut per_line = vec![0; lines.len()];
+    for (span, &count) in map.spans().iter().zip(counts) {
+        per_line[span.line - 1] += count;
+    }
+
+    let total: u64 = counts.iter().sum();
+    writeln!(out, "{} steps", total)?;
+    let width = total.to_string().len().max("steps".len());
+    let line_width = lines.len().to_string().len().max("line".len());
+    writeln!(out, "{:>line_width$} {:>width$} | source", "line", "steps")?;
+    for (n, (line, count)) in lines.iter().zip(&per_line).enumerate() {
+        // Lines without instructions would only be noise
+        if map.spans().iter().any(|span| span.line == n + 1) {
+            writeln!(out, "{:>line_width$} {:>width$} | {}", n + 1, count, line)?;
+        }
+    }
+
+    let mut hottest: Vec<_> = (0..counts.len().min(map.len()))
+        .filter(|&ip| counts[ip] > 0)
+        .collect();
+    // Stable, so ties stay in program order
+    hottest.sort_by_key(|&ip| std::cmp::Reverse(counts[ip]));
+    if hottest.is_empty() || top == 0 {
+        return Ok(());
+    }
+    writeln!(out, "\nhottest instructions:")?;
+    for ip in hottest.into_iter().take(top) {
+        let span = map.spans()[ip];
+        writeln!(
+            out,
+            "{:>width$}  instruction {} `{}` at {}",
+            counts[ip],
+            ip,
+            char::from(source[span.offset]),
+            span
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_mapped, ParseOptions, Program};
+
+    #[test]
+    fn profile_report() {
+        let source = b"++ set up\n\n[>+<-]\n>.";
+        let mut map = SourceMap::new("test.bf");
+        let ops = parse_mapped(&source[..], ParseOptions::default(), &mut map).unwrap();
+        let mut program = Program::with_io(ops, std::io::empty(), Vec::new());
+        let mut counts = Vec::new();
+        program.run_profiled(&mut counts).unwrap();
+        assert_eq!(counts[..3], [1, 1, 1]);
+        assert_eq!(counts.iter().sum::<u64>(), program.steps());
+
+        let mut out = Vec::new();
+        report(&mut out, source, &map, &counts, 2).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "15 steps
+line steps | source
+   1     2 | ++ set up
+   3    11 | [>+<-]
+   4     2 | >.
+
+hottest instructions:
+    2  instruction 3 `>` at 3:2
+    2  instruction 4 `+` at 3:3
+"
+        );
+    }
+}
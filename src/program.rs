@@ -0,0 +1,1135 @@
+use std::io::prelude::*;
+use std::io::{BufReader, Stdin, Stdout};
+use std::time::Instant;
+
+use crate::ir::{self, Ir};
+use crate::{
+    BfError, Cell, EofPolicy, InputMode, Limit, Limits, Operation, OutputMode, Tape, TapePolicy,
+};
+
+/// A loaded brainfuck program together with its memory.
+///
+/// `,` reads from `R` and `.` writes to `W`, by default these are stdin and stdout.
+/// Memory is made of `C` cells, by default bytes.
+pub struct Program<R = Stdin, W = Stdout, C: Cell = u8> {
+    ops: Tape<Operation>,
+    /// Index of the matching bracket for every `[` and `]`. Anything else,
+    /// including a bracket without a match, is its own partner.
+    jumps: Vec<usize>,
+    memory: Tape<C>,
+    input: BufReader<R>,
+    output: W,
+    eof: EofPolicy,
+    input_mode: InputMode,
+    output_mode: OutputMode,
+    limits: Limits,
+    /// Instructions executed so far
+    steps: u64,
+    /// Bytes written so far
+    written: u64,
+}
+
+impl Program {
+    /// Create a program that reads from stdin and writes to stdout.
+    pub fn new(program: Vec<Operation>) -> Self {
+        Self::with_io(program, std::io::stdin(), std::io::stdout())
+    }
+}
+
+impl<R: Read, W: Write> Program<R, W> {
+    /// Create a program that reads from `input` and writes to `output`.
+    pub fn with_io(program: Vec<Operation>, input: R, output: W) -> Self {
+        // Allocate some memory to start with
+        let memory = vec![0; 512];
+
+        Self {
+            jumps: jump_table(&program),
+            ops: Tape::new(program),
+            memory: Tape::new(memory),
+            input: BufReader::new(input),
+            output,
+            eof: EofPolicy::default(),
+            input_mode: InputMode::default(),
+            output_mode: OutputMode::default(),
+            limits: Limits::default(),
+            steps: 0,
+            written: 0,
+        }
+    }
+
+    /// Execute all operations like `run_ir`, but compiled to native code first.
+    ///
+    /// Only byte cells are supported. The generated code works on one block of
+    /// memory with its own cursor and doesn't count instructions, so paged
+    /// tapes, tapes that grow left or wrap around, and step or time limits are
+    /// run through `Ir` instead.
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    pub fn run_jit(&mut self) -> Result<(), BfError> {
+        let ir = ir::optimize(ir::lower(self.ops.data())?);
+        let counted = self.limits.steps.is_some() || self.limits.deadline.is_some();
+        match self.memory.policy() {
+            _ if self.memory.is_paged() || counted => self.exec(&ir)?,
+            TapePolicy::GrowBoth | TapePolicy::Wrap(_) => self.exec(&ir)?,
+            TapePolicy::GrowRight | TapePolicy::Fixed(_) => crate::jit::run(self, &ir)?,
+        }
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+impl<R: Read, W: Write, C: Cell> Program<R, W, C> {
+    /// Switch to `D` cells, clearing memory.
+    pub fn with_cell<D: Cell>(self) -> Program<R, W, D> {
+        Program {
+            ops: self.ops,
+            jumps: self.jumps,
+            memory: self.memory.cleared(self.memory.policy()),
+            input: self.input,
+            output: self.output,
+            eof: self.eof,
+            input_mode: self.input_mode,
+            output_mode: self.output_mode,
+            limits: self.limits,
+            steps: self.steps,
+            written: self.written,
+        }
+    }
+
+    /// Set what happens at the edges of memory, clearing it.
+    ///
+    /// # Panics
+    ///
+    /// If `policy` is a fixed size or wrapping tape of no cells.
+    pub fn with_tape_policy(mut self, policy: TapePolicy) -> Self {
+        self.memory = self.memory.cleared(policy);
+        self
+    }
+
+    /// Keep memory in pages that are only allocated once written to, clearing it.
+    ///
+    /// This suits programs that move the pointer a long way but only use a few cells.
+    pub fn with_paged_memory(mut self) -> Self {
+        let mut memory = Tape::paged(self.memory.policy());
+        memory.set_max_cells(self.limits.cells);
+        self.memory = memory;
+        self
+    }
+
+    /// Stop with `BfError::LimitExceeded` once the program uses more than `limits` allow.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.memory.set_max_cells(limits.cells);
+        self.limits = limits;
+        self
+    }
+
+    /// Set what `,` does once input has run out.
+    pub fn with_eof(mut self, eof: EofPolicy) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    /// Set how `,` reads input.
+    pub fn with_input_mode(mut self, input_mode: InputMode) -> Self {
+        self.input_mode = input_mode;
+        self
+    }
+
+    /// Set how `.` writes output.
+    pub fn with_output_mode(mut self, output_mode: OutputMode) -> Self {
+        self.output_mode = output_mode;
+        self
+    }
+
+    /// The stream `.` writes to.
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    /// Consume the program, returning the stream `.` wrote to.
+    pub fn into_output(self) -> W {
+        self.output
+    }
+
+    /// The instruction tape, its cursor is the next operation to execute.
+    pub fn ops(&self) -> &Tape<Operation> {
+        &self.ops
+    }
+
+    /// Number of instructions executed so far, counted like `Limits::steps`.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Number of bytes written so far, counted like `Limits::output`.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Replace the operations with `program`, ready to run from its start on
+    /// the memory as it is.
+    pub fn load(&mut self, program: Vec<Operation>) {
+        self.jumps = jump_table(&program);
+        self.ops = Tape::new(program);
+    }
+
+    /// Clear memory and rewind to the first operation, as if nothing had run.
+    pub fn reset(&mut self) {
+        self.memory = self.memory.cleared(self.memory.policy());
+        self.ops.seek(0);
+        self.steps = 0;
+        self.written = 0;
+    }
+
+    /// Index of the bracket matching the one at `ip`.
+    pub(crate) fn jump_target(&self, ip: usize) -> Option<usize> {
+        self.jumps.get(ip).copied().filter(|&target| target != ip)
+    }
+
+    /// The memory tape.
+    pub fn memory(&self) -> &Tape<C> {
+        &self.memory
+    }
+
+    /// The memory tape, to set up cells before running or change them in between.
+    pub fn memory_mut(&mut self) -> &mut Tape<C> {
+        &mut self.memory
+    }
+
+    /// bf increment `+`
+    fn inc(&mut self) -> Result<(), BfError> {
+        *self.memory.cell_mut()? = self.memory.cell().wrapping_add_i32(1);
+        Ok(())
+    }
+
+    /// bf decrement `-`
+    fn dec(&mut self) -> Result<(), BfError> {
+        *self.memory.cell_mut()? = self.memory.cell().wrapping_add_i32(-1);
+        Ok(())
+    }
+
+    /// bf move left `<`
+    fn mvl(&mut self) -> Result<(), BfError> {
+        self.memory.mv_left()
+    }
+
+    /// bf move right `>`
+    fn mvr(&mut self) -> Result<(), BfError> {
+        self.memory.mv_right()
+    }
+
+    /// bf jump backward `]`
+    fn jpb(&mut self) -> Result<(), BfError> {
+        if !self.memory.cell().is_zero() {
+            let start = self.ops.cursor();
+            let target = self
+                .jump_target(start)
+                .ok_or(BfError::UnmatchedClose(start))?;
+            self.ops.seek(target);
+        }
+        Ok(())
+    }
+
+    /// bf jump foward `[`
+    fn jpf(&mut self) -> Result<(), BfError> {
+        if self.memory.cell().is_zero() {
+            let start = self.ops.cursor();
+            let target = self
+                .jump_target(start)
+                .ok_or(BfError::UnmatchedOpen(start))?;
+            self.ops.seek(target);
+        }
+        Ok(())
+    }
+
+    /// bf output `.`
+    pub(crate) fn prt(&mut self) -> Result<(), BfError> {
+        let cell = *self.memory.cell();
+        // Format first so the output limit can be checked before anything is written
+        let mut buff = [0; 16];
+        let mut rest = &mut buff[..];
+        match self.output_mode {
+            OutputMode::Raw => rest.write_all(&[cell.to_byte()])?,
+            OutputMode::Decimal => writeln!(rest, "{}", cell)?,
+            OutputMode::Hex => writeln!(rest, "{:02x}", cell)?,
+            OutputMode::Latin1 => write!(rest, "{}", char::from(cell.to_byte()))?,
+        }
+        let unused = rest.len();
+        let len = buff.len() - unused;
+
+        self.written += len as u64;
+        if self.limits.output.is_some_and(|max| self.written > max) {
+            return Err(BfError::LimitExceeded(Limit::Output, None));
+        }
+        self.output.write_all(&buff[..len])?;
+        Ok(())
+    }
+
+    /// bf input `,`
+    pub(crate) fn inp(&mut self) -> Result<(), BfError> {
+        // Make sure any prompt is visible before blocking on input
+        if self.input.buffer().is_empty() {
+            self.output.flush()?;
+        }
+
+        let value = match self.input_mode {
+            InputMode::Byte => self.read_byte()?,
+            InputMode::DecimalLine => self.read_decimal()?,
+        };
+        match (value, self.eof) {
+            (Some(value), _) => *self.memory.cell_mut()? = value,
+            (None, EofPolicy::Unchanged) => {}
+            (None, EofPolicy::Zero) => *self.memory.cell_mut()? = C::default(),
+            (None, EofPolicy::MinusOne) => {
+                *self.memory.cell_mut()? = C::default().wrapping_add_i32(-1)
+            }
+        }
+        Ok(())
+    }
+
+    /// Read one byte of input, `None` at the end of input.
+    fn read_byte(&mut self) -> Result<Option<C>, BfError> {
+        let mut byte = [0];
+        loop {
+            match self.input.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(C::from_byte(byte[0]))),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Read a line of input as a decimal number, `None` at the end of input.
+    fn read_decimal(&mut self) -> Result<Option<C>, BfError> {
+        let mut buff = String::new();
+        if self.input.read_line(&mut buff)? == 0 {
+            return Ok(None);
+        }
+
+        let buff = buff.trim();
+        let value = buff
+            .parse()
+            .map_err(|_| BfError::InputError(buff.to_string()))?;
+        Ok(Some(value))
+    }
+
+    /// bf debug dump `#`, written to stderr so it doesn't mix with the program's output
+    pub(crate) fn dmp(&mut self) -> Result<(), BfError> {
+        let mut err = std::io::stderr().lock();
+        writeln!(err, "# pointer at cell {}", self.memory.position())?;
+        self.memory.dump(&mut err, 8)?;
+        Ok(())
+    }
+
+    /// Execute the current operation. Should not be used directly, use `step` instead.
+    fn operate(&mut self) -> Result<(), BfError> {
+        match *self.ops.cell() {
+            Operation::Increment => self.inc()?,
+            Operation::Decrement => self.dec()?,
+            Operation::MoveLeft => self.mvl()?,
+            Operation::MoveRight => self.mvr()?,
+            Operation::Output => self.prt()?,
+            Operation::Input => self.inp()?,
+            Operation::JumpForward => self.jpf()?,
+            Operation::JumpBack => self.jpb()?,
+            Operation::DebugDump => self.dmp()?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Count an executed instruction against the limits.
+    fn tick(&mut self) -> Result<(), BfError> {
+        self.steps += 1;
+        if self.limits.steps.is_some_and(|max| self.steps > max) {
+            return Err(BfError::LimitExceeded(Limit::Steps, None));
+        }
+        // Reading the clock is slow compared to an instruction, so only do it now and then
+        if self.steps.is_multiple_of(256)
+            && self.limits.deadline.is_some_and(|d| Instant::now() >= d)
+        {
+            return Err(BfError::LimitExceeded(Limit::Deadline, None));
+        }
+        Ok(())
+    }
+
+    /// Execute the next operation
+    pub fn step(&mut self) -> Result<(), BfError> {
+        let ip = self.ops.cursor();
+        self.tick().map_err(|e| e.at(ip))?;
+        self.operate().map_err(|e| e.at(ip))?;
+        self.ops.mv_right()?;
+        Ok(())
+    }
+
+    /// Execute all operations
+    pub fn run(&mut self) -> Result<(), BfError> {
+        while *self.ops.cell() != Operation::NoOp {
+            self.step()?;
+        }
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Execute all operations like `run`, adding one to `counts[ip]` every
+    /// time the operation at `ip` is executed.
+    ///
+    /// `counts` is grown to one entry per operation, and keeps what was
+    /// counted if the program fails.
+    pub fn run_profiled(&mut self, counts: &mut Vec<u64>) -> Result<(), BfError> {
+        let len = self.ops.data().len();
+        if counts.len() < len {
+            counts.resize(len, 0);
+        }
+        while *self.ops.cell() != Operation::NoOp {
+            counts[self.ops.cursor()] += 1;
+            self.step()?;
+        }
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Execute all operations like `run`, but through the folded and optimized `Ir`.
+    ///
+    /// This always starts from the first operation and can't be mixed with `step`.
+    pub fn run_ir(&mut self) -> Result<(), BfError> {
+        let ir = ir::optimize(ir::lower(self.ops.data())?);
+        self.exec(&ir)?;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Execute a block of IR.
+    fn exec(&mut self, ir: &[(Ir, usize)]) -> Result<(), BfError> {
+        for (i, &(_, at)) in ir.iter().enumerate() {
+            match self.exec_one(&ir[i..]) {
+                Err(
+                    e @ (BfError::PointerOutOfBounds(None)
+                    | BfError::LimitExceeded(Limit::Cells, None)),
+                ) => return Err(self.replay(e, at)),
+                result => result.map_err(|e| e.at(at))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute the first instruction of `ir`, the rest is only looked at.
+    fn exec_one(&mut self, ir: &[(Ir, usize)]) -> Result<(), BfError> {
+        self.tick()?;
+        match &ir[0].0 {
+            Ir::Add(delta) => {
+                let cell = self.memory.cell_mut()?;
+                *cell = cell.wrapping_add_i32(*delta);
+            }
+            Ir::Move(n) => self.memory.mv(*n)?,
+            Ir::AddAt { offset, delta } => {
+                let cell = self.memory.offset_mut(*offset)?;
+                *cell = cell.wrapping_add_i32(*delta);
+            }
+            Ir::Check { low, high } => self.memory.check(*low, *high)?,
+            Ir::Output => self.prt()?,
+            Ir::Input => self.inp()?,
+            Ir::DebugDump => self.dmp()?,
+            Ir::Loop(body) => {
+                while !self.memory.cell().is_zero() {
+                    self.tick()?;
+                    self.exec(body)?;
+                }
+            }
+            Ir::SetZero => *self.memory.cell_mut()? = C::default(),
+            Ir::MulAdd { offset, factor } => {
+                // The loop this came from would not have run at all
+                let value = *self.memory.cell();
+                if !value.is_zero() {
+                    // Check the cells of the ones still to come too, so nothing
+                    // has changed yet if the loop would have left the tape
+                    let offsets = ir.iter().map_while(|(next, _)| match next {
+                        Ir::MulAdd { offset, .. } => Some(*offset),
+                        _ => None,
+                    });
+                    let (low, high) = offsets.fold((0, 0), |(low, high), offset| {
+                        (low.min(offset), high.max(offset))
+                    });
+                    self.memory.check(low, high)?;
+                    let cell = self.memory.offset_mut(*offset)?;
+                    *cell = cell.wrapping_mul_add(value, *factor);
+                }
+            }
+            // A wrapping tape without a zero cell goes round forever
+            Ir::ScanRight => {
+                while !self.memory.cell().is_zero() {
+                    self.tick()?;
+                    self.memory.scan_right()?;
+                }
+            }
+            Ir::ScanLeft => {
+                while !self.memory.cell().is_zero() {
+                    self.tick()?;
+                    self.memory.scan_left()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Find where an IR instruction folded from the operations from `at` on
+    /// left the tape, or ran out of memory, by running those operations one at
+    /// a time instead, leaving the program where `run` would have failed.
+    ///
+    /// IR fails before changing anything the operations would have changed
+    /// before failing, so only the deferred pointer moves need catching up on.
+    pub(crate) fn replay(&mut self, error: BfError, at: usize) -> BfError {
+        let folded = |op: &Operation| {
+            matches!(
+                op,
+                Operation::Increment
+                    | Operation::Decrement
+                    | Operation::MoveLeft
+                    | Operation::MoveRight
+            )
+        };
+        let ops = self.ops.data();
+        // Moves are deferred from the last operation that needed the pointer in place
+        let offset: isize = match ops.get(at) {
+            Some(op) if folded(op) => ops[..at]
+                .iter()
+                .rev()
+                .take_while(|op| folded(op))
+                .map(|op| match op {
+                    Operation::MoveRight => 1,
+                    Operation::MoveLeft => -1,
+                    _ => 0,
+                })
+                .sum(),
+            _ => 0,
+        };
+        if self.memory.mv(offset).is_err() {
+            return error.at(at);
+        }
+
+        // The failure is before the next operation that needs the pointer in
+        // place, or somewhere in a loop that was rewritten
+        let end = match ops.get(at) {
+            Some(Operation::JumpForward) => self.jump_target(at),
+            _ => None,
+        };
+        self.ops.seek(at);
+        loop {
+            if let Err(e) = self.step() {
+                return e;
+            }
+            let in_loop = end.is_some_and(|end| self.ops.cursor() <= end);
+            if !in_loop && !folded(self.ops.cell()) {
+                return error.at(at);
+            }
+        }
+    }
+}
+
+/// Pair up the brackets in `ops` so jumps don't have to search for their partner.
+fn jump_table(ops: &[Operation]) -> Vec<usize> {
+    let mut jumps: Vec<_> = (0..ops.len()).collect();
+    let mut open = Vec::new();
+
+    for (i, op) in ops.iter().enumerate() {
+        match op {
+            Operation::JumpForward => open.push(i),
+            Operation::JumpBack => {
+                if let Some(j) = open.pop() {
+                    jumps[i] = j;
+                    jumps[j] = i;
+                }
+            }
+            _ => {}
+        }
+    }
+    jumps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prog_inc() {
+        let ops = vec![Operation::Increment];
+        let mut prog = Program::new(ops);
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), 1);
+    }
+
+    #[test]
+    fn prog_dec() {
+        let ops = vec![Operation::Increment, Operation::Decrement];
+        let mut prog = Program::new(ops);
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), 0);
+    }
+
+    #[test]
+    fn prog_inc_wrapping() {
+        let ops = vec![Operation::Increment];
+        let mut prog = Program::new(ops);
+        *prog.memory.cell_mut().unwrap() = 255;
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), 0);
+    }
+
+    #[test]
+    fn prog_dec_wrapping() {
+        let ops = vec![Operation::Decrement];
+        let mut prog = Program::new(ops);
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), 255);
+    }
+
+    #[test]
+    fn prog_step() {
+        let ops = vec![Operation::Decrement, Operation::Increment];
+        let mut prog = Program::new(ops);
+        prog.step().unwrap();
+        assert_eq!(*prog.memory.cell(), 255);
+        prog.step().unwrap();
+        assert_eq!(*prog.memory.cell(), 0);
+    }
+
+    #[test]
+    fn prog_jmp() {
+        let ops = vec![
+            Operation::Increment,
+            Operation::JumpForward,
+            Operation::JumpBack,
+        ];
+        let mut prog = Program::new(ops);
+        prog.step().unwrap();
+        assert_eq!(*prog.ops.cell(), Operation::JumpForward);
+        prog.step().unwrap();
+        assert_eq!(*prog.ops.cell(), Operation::JumpBack);
+        prog.step().unwrap();
+        assert_eq!(*prog.ops.cell(), Operation::JumpBack);
+    }
+
+    #[test]
+    fn prog_jmp_nested() {
+        let ops = vec![
+            Operation::Increment,
+            Operation::JumpForward,
+            Operation::JumpForward,
+            Operation::Decrement,
+            Operation::JumpBack,
+            Operation::JumpBack,
+        ];
+        let mut prog = Program::new(ops);
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), 0);
+    }
+
+    #[test]
+    fn prog_ops_extends() {
+        let ops = vec![Operation::Increment];
+        let mut prog = Program::new(ops);
+        prog.step().unwrap();
+        prog.step().unwrap();
+        assert_eq!(*prog.ops.cell(), Operation::NoOp);
+    }
+
+    #[test]
+    fn prog_output() {
+        let ops = vec![Operation::Increment, Operation::Output];
+        let mut prog = Program::with_io(ops, std::io::empty(), Vec::new());
+        prog.run().unwrap();
+        assert_eq!(prog.output(), &[1]);
+    }
+
+    #[test]
+    fn prog_output_modes() {
+        let ops = vec![Operation::Decrement, Operation::Output];
+        let output = |mode| {
+            let mut prog =
+                Program::with_io(ops.clone(), std::io::empty(), Vec::new()).with_output_mode(mode);
+            prog.run().unwrap();
+            prog.output
+        };
+        assert_eq!(output(OutputMode::Raw), [0xff]);
+        assert_eq!(output(OutputMode::Decimal), b"255\n");
+        assert_eq!(output(OutputMode::Hex), b"ff\n");
+        assert_eq!(output(OutputMode::Latin1), "\u{ff}".as_bytes());
+    }
+
+    #[test]
+    fn prog_output_wide_cells() {
+        let ops = vec![Operation::Decrement, Operation::Output];
+        let mut prog = Program::with_io(ops.clone(), std::io::empty(), Vec::new())
+            .with_cell::<i16>()
+            .with_output_mode(OutputMode::Decimal);
+        prog.run().unwrap();
+        assert_eq!(prog.output, b"-1\n");
+
+        let mut prog = Program::with_io(ops, std::io::empty(), Vec::new()).with_cell::<u16>();
+        prog.run().unwrap();
+        assert_eq!(prog.output, [0xff]);
+    }
+
+    #[test]
+    fn prog_input() {
+        let ops = vec![Operation::Input, Operation::Increment, Operation::Input];
+        let mut prog = Program::with_io(ops, "A\n".as_bytes(), std::io::sink());
+        prog.step().unwrap();
+        assert_eq!(*prog.memory.cell(), b'A');
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), b'\n');
+    }
+
+    #[test]
+    fn prog_input_decimal() {
+        let ops = vec![Operation::Input, Operation::Increment];
+        let mut prog = Program::with_io(ops, "41\n".as_bytes(), std::io::sink())
+            .with_input_mode(InputMode::DecimalLine);
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), 42);
+    }
+
+    /// Run `src` through `run`, `run_ir` and `run_jit` and check they agree.
+    fn assert_same(src: &str, input: &str) {
+        assert_same_with(src, input, TapePolicy::default());
+    }
+
+    /// Like `assert_same`, on a tape with `policy`.
+    fn assert_same_with(src: &str, input: &str, policy: TapePolicy) {
+        let ops = crate::parse(src.as_bytes()).unwrap();
+
+        let mut naive =
+            Program::with_io(ops.clone(), input.as_bytes(), Vec::new()).with_tape_policy(policy);
+        let mut folded =
+            Program::with_io(ops, input.as_bytes(), Vec::new()).with_tape_policy(policy);
+        let naive_result = naive.run().map_err(|e| e.to_string());
+        let folded_result = folded.run_ir().map_err(|e| e.to_string());
+
+        assert_eq!(naive_result, folded_result, "{}", src);
+        assert_eq!(naive.memory.cursor(), folded.memory.cursor(), "{}", src);
+        let len = naive.memory.data().len().min(folded.memory.data().len());
+        assert_eq!(
+            naive.memory.data()[..len],
+            folded.memory.data()[..len],
+            "{}",
+            src
+        );
+        assert_eq!(naive.output, folded.output, "{}", src);
+
+        #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+        {
+            let ops = crate::parse(src.as_bytes()).unwrap();
+            let mut jit =
+                Program::with_io(ops, input.as_bytes(), Vec::new()).with_tape_policy(policy);
+            let jit_result = jit.run_jit().map_err(|e| e.to_string());
+
+            assert_eq!(naive_result, jit_result, "{}", src);
+            assert_eq!(naive.memory.cursor(), jit.memory.cursor(), "{}", src);
+            let len = naive.memory.data().len().min(jit.memory.data().len());
+            assert_eq!(
+                naive.memory.data()[..len],
+                jit.memory.data()[..len],
+                "{}",
+                src
+            );
+            assert_eq!(naive.output, jit.output, "{}", src);
+        }
+    }
+
+    /// Like `assert_same`, with `limits`, on dense and paged tapes that grow
+    /// right or both ways.
+    fn assert_same_limited(src: &str, limits: Limits) {
+        let ops = crate::parse(src.as_bytes()).unwrap();
+        for policy in [TapePolicy::GrowRight, TapePolicy::GrowBoth] {
+            for paged in [false, true] {
+                let program = || {
+                    let prog = Program::with_io(ops.clone(), std::io::empty(), Vec::new())
+                        .with_tape_policy(policy)
+                        .with_limits(limits);
+                    if paged {
+                        prog.with_paged_memory()
+                    } else {
+                        prog
+                    }
+                };
+                let context = format!("{} {:?} paged: {}", src, policy, paged);
+                let (mut naive, mut folded) = (program(), program());
+                let naive_result = naive.run().map_err(|e| e.to_string());
+                assert_eq!(
+                    naive_result,
+                    folded.run_ir().map_err(|e| e.to_string()),
+                    "{}",
+                    context
+                );
+                assert_eq!(
+                    naive.memory.position(),
+                    folded.memory.position(),
+                    "{}",
+                    context
+                );
+
+                #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+                {
+                    let mut jit = program();
+                    assert_eq!(
+                        naive_result,
+                        jit.run_jit().map_err(|e| e.to_string()),
+                        "{}",
+                        context
+                    );
+                    assert_eq!(
+                        naive.memory.position(),
+                        jit.memory.position(),
+                        "{}",
+                        context
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn prog_ir_matches() {
+        assert_same("+", "");
+        assert_same("+-", "");
+        assert_same("-", "");
+        assert_same("+[[-]]", "");
+        assert_same(&">".repeat(1000), "");
+        assert_same("++>+++[<+>-]<.", "");
+        assert_same(",>,[<+>-]<.", "30\n35\n");
+        assert_same(
+            "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.",
+            "",
+        );
+    }
+
+    #[test]
+    fn prog_ir_idioms() {
+        assert_same("+++[-]", "");
+        assert_same("++[+]", "");
+        assert_same("+++++[->++>+++<<]>.>.", "");
+        assert_same("[->+<]>.", "");
+        assert_same("+++[+>-<]>.", "");
+        assert_same(">+>+>+>+<<<<[>]>+", "");
+        assert_same(">>>+>+<[<]+", "");
+        assert_same(">+>+>+[<]", "");
+        assert_same(&format!("{}[>]", "+>".repeat(600)), "");
+        assert_same("+[<+>-]", "");
+        assert_same("[<+>-]", "");
+    }
+
+    #[test]
+    fn prog_ir_underflow() {
+        assert_same("<", "");
+        assert_same(">+<<+>", "");
+        assert_same("<+>", "");
+        assert_same("<>", "");
+        assert_same("+[<>-]", "");
+        assert_same("+<+->+", "");
+        assert_same(">.<<>>", "");
+        assert_same("+[-<+>]", "");
+        assert_same("++[->+<<+>]", "");
+        assert_same_with(">>><", "", TapePolicy::Fixed(3));
+        assert_same_with("+>>>[>]", "", TapePolicy::Fixed(3));
+        assert_same_with(">>+[->++>+<<]", "", TapePolicy::Fixed(4));
+    }
+
+    #[test]
+    fn prog_ir_tape_policies() {
+        for policy in [
+            TapePolicy::GrowBoth,
+            TapePolicy::Wrap(8),
+            TapePolicy::Fixed(8),
+        ] {
+            assert_same_with("<+<<+>>>[<]", "", policy);
+            assert_same_with("+>+>+<<<<[-<+>]+[>]", "", policy);
+            assert_same_with(&format!("{}[-]", "->".repeat(6)), "", policy);
+        }
+        assert_same_with(
+            &format!("{}<<[-]", "->".repeat(10)),
+            "",
+            TapePolicy::Wrap(8),
+        );
+    }
+
+    #[test]
+    fn prog_paged_matches() {
+        let srcs = [
+            "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.",
+            "+++[->+<]>[>]<[<]<+",
+            ">>>>+[<+]",
+        ];
+        for policy in [TapePolicy::GrowRight, TapePolicy::Wrap(8)] {
+            for src in srcs {
+                let ops = crate::parse(src.as_bytes()).unwrap();
+                let mut dense = Program::with_io(ops.clone(), std::io::empty(), Vec::new())
+                    .with_tape_policy(policy);
+                let mut paged = Program::with_io(ops, std::io::empty(), Vec::new())
+                    .with_tape_policy(policy)
+                    .with_paged_memory();
+                let dense_result = dense.run_ir().map_err(|e| e.to_string());
+                let paged_result = paged.run_ir().map_err(|e| e.to_string());
+
+                assert_eq!(dense_result, paged_result, "{}", src);
+                assert_eq!(dense.memory.position(), paged.memory.position(), "{}", src);
+                for position in -1..300 {
+                    assert_eq!(
+                        dense.memory.get(position),
+                        paged.memory.get(position),
+                        "{}",
+                        src
+                    );
+                }
+                assert_eq!(dense.output, paged.output, "{}", src);
+            }
+        }
+    }
+
+    #[test]
+    fn prog_limits() {
+        let limited = |src: &str, limits| {
+            let ops = crate::parse(src.as_bytes()).unwrap();
+            Program::with_io(ops, std::io::empty(), Vec::new()).with_limits(limits)
+        };
+        let steps = Limits {
+            steps: Some(100),
+            ..Limits::default()
+        };
+        let mut prog = limited("+[]", steps);
+        assert!(matches!(
+            prog.run(),
+            Err(BfError::LimitExceeded(Limit::Steps, Some(2)))
+        ));
+        assert_eq!(prog.steps(), 101);
+        let mut prog = limited("+[]", steps);
+        assert!(matches!(
+            prog.run_ir(),
+            Err(BfError::LimitExceeded(Limit::Steps, Some(1)))
+        ));
+
+        let output = Limits {
+            output: Some(3),
+            ..Limits::default()
+        };
+        let mut prog = limited("+[.]", output);
+        assert!(matches!(
+            prog.run(),
+            Err(BfError::LimitExceeded(Limit::Output, Some(2)))
+        ));
+        assert_eq!(prog.output, [1, 1, 1]);
+        #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+        {
+            let mut prog = limited("+[.]", output);
+            assert!(matches!(
+                prog.run_jit(),
+                Err(BfError::LimitExceeded(Limit::Output, Some(2)))
+            ));
+        }
+
+        let cells = Limits {
+            cells: Some(1000),
+            ..Limits::default()
+        };
+        let mut prog = limited("+[>+]", cells);
+        assert!(matches!(
+            prog.run_ir(),
+            Err(BfError::LimitExceeded(Limit::Cells, Some(2)))
+        ));
+        assert_eq!(prog.memory.data().len(), 1000);
+
+        // Whichever order the tape is set up in
+        let src = format!("+{}+", ">".repeat(1000));
+        let mut prog = limited(&src, cells).with_paged_memory();
+        assert!(matches!(
+            prog.run(),
+            Err(BfError::LimitExceeded(Limit::Cells, Some(1000)))
+        ));
+        assert_eq!(prog.memory.position(), 999);
+
+        // Less than the tape starts out with
+        let few = Limits {
+            cells: Some(10),
+            ..Limits::default()
+        };
+        let src = format!("{}+", ">".repeat(20));
+        let mut prog = limited(&src, few);
+        assert!(matches!(
+            prog.run(),
+            Err(BfError::LimitExceeded(Limit::Cells, Some(9)))
+        ));
+        assert_eq!(prog.memory.cursor(), 9);
+        assert_same_limited(&src, few);
+        assert_same_limited(&format!("{}+", ">".repeat(9)), few);
+        assert_same_limited("+>+>+>+<<<[>]", few);
+        assert_same_limited(&format!("+[{}+]", ">".repeat(3)), few);
+        // A check that fails partway leaves the cap where it was
+        let two = Limits {
+            cells: Some(2),
+            ..Limits::default()
+        };
+        assert_same_limited("+><<-++>+", two);
+        assert_same_limited("+[<+>>+<-]", two);
+        // Far less than a page
+        let mut prog = limited(&src, few).with_paged_memory();
+        assert!(matches!(
+            prog.run(),
+            Err(BfError::LimitExceeded(Limit::Cells, Some(9)))
+        ));
+        assert_eq!(prog.memory.cursor(), 9);
+        let mut prog = limited("+>+", few).with_paged_memory();
+        prog.run().unwrap();
+
+        let past = Limits {
+            deadline: Some(Instant::now()),
+            ..Limits::default()
+        };
+        let mut prog = limited("+[]", past);
+        assert!(matches!(
+            prog.run(),
+            Err(BfError::LimitExceeded(Limit::Deadline, Some(_)))
+        ));
+    }
+
+    #[test]
+    fn prog_wide_cells() {
+        let ops = vec![Operation::Decrement];
+        let mut prog = Program::new(ops).with_cell::<u16>();
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), u16::MAX);
+
+        let ops = vec![Operation::Increment; 256];
+        let mut prog = Program::new(ops).with_cell::<u32>();
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), 256);
+    }
+
+    #[test]
+    fn prog_signed_cells() {
+        let ops = vec![Operation::Input, Operation::Decrement];
+        let mut prog = Program::with_io(ops, "-5\n".as_bytes(), std::io::sink())
+            .with_input_mode(InputMode::DecimalLine)
+            .with_cell::<i8>();
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), -6);
+    }
+
+    #[test]
+    fn prog_wide_cells_ir() {
+        // 16 * 16 * 16 overflows a byte but not a u16
+        let src = "++++++++++++++++[>++++++++++++++++[>++++++++++++++++<-]<-]>>[->+>+<<]";
+        let ops = crate::parse(src.as_bytes()).unwrap();
+
+        let mut naive = Program::new(ops.clone()).with_cell::<u16>();
+        naive.run().unwrap();
+        let mut folded = Program::new(ops).with_cell::<u16>();
+        folded.run_ir().unwrap();
+
+        assert_eq!(naive.memory.data()[..5], [0, 0, 0, 4096, 4096]);
+        assert_eq!(naive.memory.data(), folded.memory.data());
+        assert_eq!(naive.memory.cursor(), folded.memory.cursor());
+    }
+
+    #[test]
+    fn prog_jump_table() {
+        let ops = vec![
+            Operation::JumpBack,
+            Operation::JumpForward,
+            Operation::JumpForward,
+            Operation::JumpBack,
+        ];
+        assert_eq!(jump_table(&ops), vec![0, 1, 3, 2]);
+    }
+
+    #[test]
+    fn prog_bad_input() {
+        let ops = vec![Operation::Input];
+        let mut prog = Program::with_io(ops, "abc\n".as_bytes(), std::io::sink())
+            .with_input_mode(InputMode::DecimalLine);
+        assert!(matches!(prog.run(), Err(BfError::InputError(_))));
+    }
+
+    #[test]
+    fn prog_eof_unchanged() {
+        let ops = vec![Operation::Increment, Operation::Input];
+        let mut prog = Program::with_io(ops, std::io::empty(), std::io::sink());
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), 1);
+    }
+
+    #[test]
+    fn prog_eof_zero() {
+        let ops = vec![Operation::Increment, Operation::Input, Operation::Input];
+        let mut prog =
+            Program::with_io(ops, "7".as_bytes(), std::io::sink()).with_eof(EofPolicy::Zero);
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), 0);
+    }
+
+    #[test]
+    fn prog_eof_minus_one() {
+        let ops = vec![Operation::Input];
+        let mut prog =
+            Program::with_io(ops, std::io::empty(), std::io::sink()).with_eof(EofPolicy::MinusOne);
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), 255);
+
+        let ops = vec![Operation::Input];
+        let mut prog = Program::with_io(ops, std::io::empty(), std::io::sink())
+            .with_cell::<i16>()
+            .with_eof(EofPolicy::MinusOne);
+        prog.run().unwrap();
+        assert_eq!(*prog.memory.cell(), -1);
+    }
+
+    #[test]
+    fn prog_unmatched_open() {
+        let ops = vec![Operation::JumpForward, Operation::Increment];
+        let mut prog = Program::new(ops);
+        assert!(matches!(prog.run(), Err(BfError::UnmatchedOpen(0))));
+    }
+
+    #[test]
+    fn prog_unmatched_close() {
+        let ops = vec![Operation::Increment, Operation::JumpBack];
+        let mut prog = Program::new(ops);
+        assert!(matches!(prog.run(), Err(BfError::UnmatchedClose(1))));
+    }
+
+    #[test]
+    fn prog_underflow() {
+        let ops = vec![Operation::MoveLeft];
+        let mut prog = Program::new(ops);
+        assert!(matches!(
+            prog.run(),
+            Err(BfError::PointerOutOfBounds(Some(0)))
+        ));
+    }
+
+    #[test]
+    fn prog_debug_dump() {
+        let ops = vec![
+            Operation::Increment,
+            Operation::DebugDump,
+            Operation::Output,
+        ];
+        let mut prog = Program::with_io(ops, std::io::empty(), Vec::new());
+        prog.run().unwrap();
+        assert_eq!(prog.output, [1]);
+        assert_same("+>#<#.", "");
+    }
+
+    #[test]
+    fn prog_empty() {
+        let mut prog = Program::new(vec![]);
+        prog.run().unwrap();
+    }
+
+    #[test]
+    fn prog_mem_extends() {
+        let mut ops = vec![];
+        ops.resize_with(1000, || Operation::MoveRight);
+        let mut prog = Program::new(ops);
+        prog.run().unwrap();
+        assert_eq!(prog.memory.cursor(), 1000);
+    }
+}
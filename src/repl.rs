@@ -0,0 +1,228 @@
+//! A read-eval-print loop that runs each line of brainfuck on the same memory.
+//!
+//! A line that leaves a loop open is continued on the next ones until it is
+//! closed. Lines starting with `:` are commands:
+//!
+//! - `:reset`: clear memory
+//! - `:tape [radius]`: show the cells around the pointer in hex and decimal
+//! - `:load <file>`: run a file of brainfuck
+//! - `:help`: list the commands
+//! - `:quit`: stop
+use std::io::prelude::*;
+
+use crate::{diagnose, parse_with, BfError, Cell, ParseOptions, Program};
+
+const HELP: &str = "commands:
+    :reset          clear memory
+    :tape [radius]  show the cells around the pointer
+    :load <file>    run a file of brainfuck
+    :help           show this message
+    :quit           stop";
+
+/// Cells shown either side of the pointer without a radius.
+const RADIUS: usize = 8;
+
+/// Runs lines of brainfuck against one program's memory.
+pub struct Repl<'p, R, W, C: Cell> {
+    program: &'p mut Program<R, W, C>,
+    options: ParseOptions,
+    /// Source of a loop that hasn't been closed yet
+    pending: String,
+}
+
+impl<'p, R: Read, W: Write, C: Cell> Repl<'p, R, W, C> {
+    /// Run lines on the memory of `program`, parsing them with `options`.
+    pub fn new(program: &'p mut Program<R, W, C>, options: ParseOptions) -> Self {
+        Self {
+            program,
+            options,
+            pending: String::new(),
+        }
+    }
+
+    /// Read and run lines from `lines` until it ends or says to quit, writing
+    /// a prompt and the state of memory to `out`.
+    pub fn run<I: BufRead, O: Write>(&mut self, lines: I, mut out: O) -> Result<(), BfError> {
+        writeln!(out, "type `:help` for a list of commands")?;
+        let mut lines = lines.lines();
+        loop {
+            let prompt = if self.pending.is_empty() {
+                "bf> "
+            } else {
+                "... "
+            };
+            write!(out, "{}", prompt)?;
+            out.flush()?;
+            let Some(line) = lines.next() else {
+                writeln!(out)?;
+                return Ok(());
+            };
+            if !self.line(&line?, &mut out)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Run a single line, returning `false` if it was `:quit`.
+    pub fn line<O: Write>(&mut self, line: &str, out: &mut O) -> Result<bool, BfError> {
+        if let Some(command) = line.trim().strip_prefix(':') {
+            return self.command(command, out);
+        }
+
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        if !open(&self.pending) {
+            let source = std::mem::take(&mut self.pending);
+            self.eval(source.as_bytes(), "<repl>", out)?;
+        }
+        Ok(true)
+    }
+
+    fn command<O: Write>(&mut self, command: &str, out: &mut O) -> Result<bool, BfError> {
+        let mut words = command.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let arg = words.next();
+        match command {
+            "reset" => {
+                self.pending.clear();
+                self.program.reset();
+                self.show(out)?;
+            }
+            "tape" => match arg.map_or(Ok(RADIUS), str::parse) {
+                Ok(radius) => self.program.memory().dump(out, radius)?,
+                Err(_) => writeln!(out, "expected a number of cells")?,
+            },
+            "load" => match arg {
+                Some(path) => match std::fs::read(path) {
+                    Ok(source) => self.eval(&source, path, out)?,
+                    Err(e) => writeln!(out, "error: {}: {}", path, e)?,
+                },
+                None => writeln!(out, "expected a file to load")?,
+            },
+            "help" => writeln!(out, "{}", HELP)?,
+            "quit" => return Ok(false),
+            command => writeln!(
+                out,
+                "unknown command `:{}`, type `:help` for a list",
+                command
+            )?,
+        }
+        Ok(true)
+    }
+
+    /// Parse and run `source`, then show the memory it left behind.
+    ///
+    /// Errors in the program are reported to `out` rather than returned.
+    fn eval<O: Write>(&mut self, source: &[u8], file: &str, out: &mut O) -> Result<(), BfError> {
+        let ops = match parse_with(source, self.options) {
+            Ok(ops) => ops,
+            Err(BfError::UnmatchedOpen(_) | BfError::UnmatchedClose(_)) => {
+                for diagnostic in diagnose(source, self.options) {
+                    write!(out, "{}", diagnostic.render(file, source))?;
+                }
+                return Ok(());
+            }
+            Err(e) => {
+                writeln!(out, "error: {}", e)?;
+                return Ok(());
+            }
+        };
+
+        let written = self.program.written();
+        self.program.load(ops);
+        let result = self.program.run();
+        // Start on a new line after any output
+        if self.program.written() != written {
+            writeln!(out)?;
+        }
+        if let Err(e) = result {
+            writeln!(out, "error: {}", e)?;
+        }
+        self.show(out)
+    }
+
+    /// Show the pointer and the cells around it.
+    fn show<O: Write>(&self, out: &mut O) -> Result<(), BfError> {
+        let memory = self.program.memory();
+        writeln!(out, "pointer at cell {}", memory.position())?;
+        memory.dump(out, RADIUS)?;
+        Ok(())
+    }
+}
+
+/// Whether `source` has a `[` that isn't closed yet.
+fn open(source: &str) -> bool {
+    let mut depth = 0;
+    for c in source.chars() {
+        match c {
+            '[' => depth += 1,
+            // An unmatched `]` can't be fixed by more lines
+            ']' if depth == 0 => return false,
+            ']' => depth -= 1,
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run `lines` in a REPL, returning what it and the program wrote.
+    fn repl(lines: &str) -> (String, String) {
+        let mut program = Program::with_io(Vec::new(), std::io::empty(), Vec::new());
+        let mut out = Vec::new();
+        Repl::new(&mut program, ParseOptions::default())
+            .run(lines.as_bytes(), &mut out)
+            .unwrap();
+        let output = String::from_utf8(program.into_output()).unwrap();
+        (String::from_utf8(out).unwrap(), output)
+    }
+
+    #[test]
+    fn repl_keeps_memory() {
+        let (out, output) = repl("+++\n>++\n<[>+<-]>.\n");
+        assert!(out.contains("pointer at cell 0\ncell  0  1"));
+        assert!(out.contains("pointer at cell 1\n"));
+        assert!(out.contains("\nhex  00 05 00"));
+        assert_eq!(output, "\u{5}");
+    }
+
+    #[test]
+    fn repl_continues_open_loops() {
+        let (out, _) = repl("++[\n>+\n<-]\n");
+        assert_eq!(out.matches("... ").count(), 2);
+        assert!(out.contains("hex  00 02"));
+    }
+
+    #[test]
+    fn repl_reports_errors() {
+        let (out, _) = repl("+]\n<\n:tape 1\n");
+        assert!(out.contains("error: unmatched `]`\n --> <repl>:1:2\n"));
+        assert!(out.contains("error: pointer moved off the tape at instruction 0\n"));
+        assert!(out.ends_with("cell  0  1\nhex  00 00\ndec   0  0\n      ^\nbf> \n"));
+    }
+
+    #[test]
+    fn repl_tape_radius() {
+        let (out, _) = repl("+\n:tape -1\n:tape 9223372036854775807\n");
+        assert!(out.contains("expected a number of cells"));
+        assert!(out.contains("cell   0   1   2"));
+    }
+
+    #[test]
+    fn repl_commands() {
+        let path = std::env::temp_dir().join("brainfuck-repl-load.bf");
+        std::fs::write(&path, "++>+").unwrap();
+        let (out, _) = repl(&format!(
+            "+++\n:reset\n:load {}\n:bogus\n:quit\n+\n",
+            path.display()
+        ));
+        assert!(out.contains("hex  00 00 00"));
+        assert!(out.contains("hex  02 01"));
+        assert!(out.contains("unknown command `:bogus`"));
+        assert!(out.ends_with("bf> "));
+        std::fs::remove_file(path).unwrap();
+    }
+}
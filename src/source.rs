@@ -0,0 +1,112 @@
+use std::fmt;
+
+/// Where an operation is in its source.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Span {
+    /// Byte offset from the start of the source.
+    pub offset: usize,
+    /// Line number, counted from 1.
+    pub line: usize,
+    /// Column in characters, counted from 1.
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// The source file of a program and the span of each of its operations,
+/// indexed like the operations themselves.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct SourceMap {
+    file: String,
+    spans: Vec<Span>,
+}
+
+impl SourceMap {
+    /// An empty map for operations parsed from `file`.
+    pub fn new(file: impl Into<String>) -> Self {
+        Self {
+            file: file.into(),
+            spans: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, span: Span) {
+        self.spans.push(span);
+    }
+
+    /// Name of the source file.
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// Span of the operation at `ip`.
+    pub fn span(&self, ip: usize) -> Option<Span> {
+        self.spans.get(ip).copied()
+    }
+
+    /// Spans of all operations.
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// Number of operations with a span.
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// `file:line:column` of the operation at `ip`.
+    pub fn locate(&self, ip: usize) -> Option<String> {
+        Some(format!("{}:{}", self.file, self.span(ip)?))
+    }
+
+    /// Index of the first operation at or after `line` and `column`.
+    pub fn find(&self, line: usize, column: usize) -> Option<usize> {
+        self.spans
+            .iter()
+            .position(|span| (span.line, span.column) >= (line, column))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_mapped, ParseOptions};
+
+    fn map(src: &str) -> SourceMap {
+        let mut map = SourceMap::new("test.bf");
+        let _ = parse_mapped(src.as_bytes(), ParseOptions::default(), &mut map);
+        map
+    }
+
+    #[test]
+    fn source_spans() {
+        let map = map("+ é-\n\n  [.]");
+        let positions: Vec<_> = map.spans().iter().map(|s| (s.line, s.column)).collect();
+        assert_eq!(positions, [(1, 1), (1, 4), (3, 3), (3, 4), (3, 5)]);
+        assert_eq!(map.span(1).unwrap().offset, 4);
+        assert_eq!(map.locate(2).as_deref(), Some("test.bf:3:3"));
+        assert_eq!(map.locate(5), None);
+    }
+
+    #[test]
+    fn source_find() {
+        let map = map("++\n  >>\n");
+        assert_eq!(map.find(1, 2), Some(1));
+        assert_eq!(map.find(2, 1), Some(2));
+        assert_eq!(map.find(3, 1), None);
+    }
+
+    #[test]
+    fn source_kept_on_error() {
+        let map = map("+\n]");
+        assert_eq!(map.locate(1).as_deref(), Some("test.bf:2:1"));
+    }
+}
@@ -0,0 +1,719 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::{BfError, Cell, Limit, TapePolicy};
+
+/// Number of cells in a page of a paged tape.
+const PAGE_SIZE: usize = 4096;
+
+/// Where the cells of a tape live.
+enum Cells<T> {
+    /// One contiguous vector, grown by doubling.
+    Dense(Vec<T>),
+    /// Pages keyed by page number, allocated the first time one of their cells is written.
+    Paged(HashMap<usize, Box<[T]>>),
+}
+
+/// A strip of cells with a cursor, growing or wrapping at the edges according to its `TapePolicy`.
+pub struct Tape<T: Default> {
+    cursor: usize,
+    cells: Cells<T>,
+    /// Index of the cell the tape started at, which moves when a dense tape grows left
+    origin: usize,
+    policy: TapePolicy,
+    /// What the cells of pages that haven't been allocated yet read as
+    zero: T,
+    /// Most cells the tape may grow to
+    max_cells: Option<usize>,
+    /// Lowest and highest positions a paged tape has reached, which `max_cells` bounds
+    reached: (isize, isize),
+}
+
+impl<T: Default + PartialEq> Tape<T> {
+    /// Move the cursor right until it is on a zero (default) cell.
+    ///
+    /// On a wrapping tape without any zero cells this goes round once, ending where it started.
+    pub fn scan_right(&mut self) -> Result<(), BfError> {
+        let data = match &mut self.cells {
+            Cells::Dense(data) => data,
+            Cells::Paged(_) => return self.scan(1),
+        };
+        let zero = T::default();
+        if let Some(i) = data[self.cursor..].iter().position(|c| *c == zero) {
+            self.cursor += i;
+            return Ok(());
+        }
+        match self.policy {
+            TapePolicy::GrowRight | TapePolicy::GrowBoth => {
+                // Everything past the end is zero
+                let len = grown(data.len(), data.len() + 1, self.max_cells)?;
+                self.cursor = data.len();
+                data.resize_with(len, T::default);
+            }
+            TapePolicy::Wrap(_) => {
+                if let Some(i) = data[..self.cursor].iter().position(|c| *c == zero) {
+                    self.cursor = i;
+                }
+            }
+            TapePolicy::Fixed(_) => {
+                self.cursor = data.len() - 1;
+                return Err(BfError::PointerOutOfBounds(None));
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the cursor left until it is on a zero (default) cell.
+    ///
+    /// On a wrapping tape without any zero cells this goes round once, ending where it started.
+    pub fn scan_left(&mut self) -> Result<(), BfError> {
+        let data = match &mut self.cells {
+            Cells::Dense(data) => data,
+            Cells::Paged(_) => return self.scan(-1),
+        };
+        let zero = T::default();
+        if let Some(i) = data[..=self.cursor].iter().rposition(|c| *c == zero) {
+            self.cursor = i;
+            return Ok(());
+        }
+        match self.policy {
+            TapePolicy::GrowRight | TapePolicy::Fixed(_) => {
+                self.cursor = 0;
+                return Err(BfError::PointerOutOfBounds(None));
+            }
+            TapePolicy::GrowBoth => {
+                // Everything before the start is zero
+                self.cursor = self.index(-(self.cursor as isize) - 1)?;
+            }
+            TapePolicy::Wrap(_) => {
+                let after = self.cursor + 1;
+                if let Some(i) = data[after..].iter().rposition(|c| *c == zero) {
+                    self.cursor = after + i;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Scan a cell at a time in the direction of `step`.
+    fn scan(&mut self, step: isize) -> Result<(), BfError> {
+        let cells = match self.policy {
+            TapePolicy::Wrap(len) => len,
+            _ => usize::MAX,
+        };
+        for _ in 0..cells {
+            if *self.cell() == self.zero {
+                break;
+            }
+            self.mv(step)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Cell> Tape<T> {
+    /// Write a table of the cells up to `radius` either side of the cursor,
+    /// in hex and decimal, with the cursor marked.
+    ///
+    /// Only cells within `extent` are shown, however large `radius` is.
+    pub(crate) fn dump<O: Write>(&self, out: &mut O, radius: usize) -> io::Result<()> {
+        let position = self.position();
+        let (first, last) = self.extent();
+        let low = position.saturating_sub_unsigned(radius).max(first);
+        let high = position.saturating_add_unsigned(radius).min(last);
+        let cells: Vec<_> = (low..=high)
+            .filter_map(|p| Some((p, *self.get(p)?)))
+            .collect();
+        let columns: Vec<_> = cells
+            .iter()
+            .map(|(p, cell)| [p.to_string(), format!("{:02x}", cell), cell.to_string()])
+            .collect();
+        let width = columns.iter().flatten().map(String::len).max().unwrap_or(0);
+
+        for (row, label) in ["cell", "hex", "dec"].iter().enumerate() {
+            write!(out, "{:4}", label)?;
+            for column in &columns {
+                write!(out, " {:>width$}", column[row])?;
+            }
+            writeln!(out)?;
+        }
+        let cursor = cells.iter().position(|&(p, _)| p == position).unwrap_or(0);
+        writeln!(
+            out,
+            "{:4}{:>pad$}",
+            "",
+            "^",
+            pad = (cursor + 1) * (width + 1)
+        )
+    }
+}
+
+impl<T: Default> Tape<T> {
+    /// A tape holding `data` that grows to the right.
+    pub fn new(data: Vec<T>) -> Self {
+        Self::with_policy(data, TapePolicy::default())
+    }
+
+    /// A tape holding `data` that follows `policy` at its edges.
+    ///
+    /// Fixed size tapes are padded or truncated to their size.
+    ///
+    /// # Panics
+    ///
+    /// If `policy` is a fixed size or wrapping tape of no cells.
+    pub fn with_policy(mut data: Vec<T>, policy: TapePolicy) -> Self {
+        assert_sized(policy);
+        if let TapePolicy::Wrap(len) | TapePolicy::Fixed(len) = policy {
+            data.truncate(len);
+            data.resize_with(len, T::default);
+        }
+        // The cursor always needs a cell to point at, even on a growing tape
+        if data.is_empty() {
+            data.push(T::default());
+        }
+        Self {
+            cursor: 0,
+            cells: Cells::Dense(data),
+            origin: 0,
+            policy,
+            zero: T::default(),
+            max_cells: None,
+            reached: (0, 0),
+        }
+    }
+
+    /// An empty tape that follows `policy` at its edges, and only allocates
+    /// memory for the pages of cells that are written to.
+    ///
+    /// # Panics
+    ///
+    /// If `policy` is a fixed size or wrapping tape of no cells.
+    pub fn paged(policy: TapePolicy) -> Self {
+        assert_sized(policy);
+        // Indices never go below zero, so start far enough in to never need to
+        let origin = match policy {
+            TapePolicy::GrowBoth => 1 << (usize::BITS - 2),
+            _ => 0,
+        };
+        Self {
+            cursor: origin,
+            cells: Cells::Paged(HashMap::new()),
+            origin,
+            policy,
+            zero: T::default(),
+            max_cells: None,
+            reached: (0, 0),
+        }
+    }
+
+    /// Move the cursor right, growing the tape if needed.
+    pub fn mv_right(&mut self) -> Result<(), BfError> {
+        self.mv(1)
+    }
+
+    /// Move the cursor left, growing the tape if needed.
+    pub fn mv_left(&mut self) -> Result<(), BfError> {
+        self.mv(-1)
+    }
+
+    /// Move the cursor `n` cells, growing the tape if needed.
+    pub fn mv(&mut self, n: isize) -> Result<(), BfError> {
+        self.cursor = self.index(n)?;
+        Ok(())
+    }
+
+    /// The cell `offset` away from the cursor, growing the tape if needed.
+    pub fn offset_mut(&mut self, offset: isize) -> Result<&mut T, BfError> {
+        let index = self.index(offset)?;
+        self.slot_mut(index)
+    }
+
+    /// Make sure the cells `low` to `high` away from the cursor are on the
+    /// tape, growing it if needed, without moving there.
+    ///
+    /// A paged tape checks both ends before recording either as reached, so
+    /// failing leaves its cell limit where it was.
+    pub(crate) fn check(&mut self, low: isize, high: isize) -> Result<(), BfError> {
+        if let Cells::Paged(_) = self.cells {
+            let (_, reached) = self.paged_index(low, self.reached)?;
+            let (_, reached) = self.paged_index(high, reached)?;
+            self.reached = reached;
+            return Ok(());
+        }
+        self.index(low)?;
+        self.index(high).map(|_| ())
+    }
+
+    /// Index of the cell `offset` away from the cursor of a paged tape, and
+    /// the positions it would have reached, given those it has reached so far.
+    fn paged_index(
+        &self,
+        offset: isize,
+        reached: (isize, isize),
+    ) -> Result<(usize, (isize, isize)), BfError> {
+        let position = match self.policy {
+            TapePolicy::Wrap(len) => (self.position() + offset).rem_euclid(len as isize),
+            TapePolicy::Fixed(len) if self.position() + offset >= len as isize => -1,
+            _ => self.position() + offset,
+        };
+        match self.origin.checked_add_signed(position) {
+            Some(index) if self.policy == TapePolicy::GrowBoth || position >= 0 => {
+                // Like a dense tape, the cap is on the stretch of cells
+                // reached, however few pages that takes
+                let (low, high) = (reached.0.min(position), reached.1.max(position));
+                match (self.policy, self.max_cells) {
+                    (TapePolicy::GrowRight | TapePolicy::GrowBoth, Some(max))
+                        if (high - low) as usize >= max =>
+                    {
+                        Err(BfError::LimitExceeded(Limit::Cells, None))
+                    }
+                    _ => Ok((index, (low, high))),
+                }
+            }
+            _ => Err(BfError::PointerOutOfBounds(None)),
+        }
+    }
+
+    /// Index of the cell `offset` away from the cursor, making sure it exists.
+    fn index(&mut self, offset: isize) -> Result<usize, BfError> {
+        let data = match &mut self.cells {
+            Cells::Dense(data) => data,
+            Cells::Paged(_) => {
+                let (index, reached) = self.paged_index(offset, self.reached)?;
+                self.reached = reached;
+                return Ok(index);
+            }
+        };
+        let len = data.len();
+        let target = self.cursor as isize + offset;
+        match self.policy {
+            TapePolicy::Wrap(_) => Ok(target.rem_euclid(len as isize) as usize),
+            TapePolicy::Fixed(_) if target < 0 || target >= len as isize => {
+                Err(BfError::PointerOutOfBounds(None))
+            }
+            TapePolicy::GrowRight if target < 0 => Err(BfError::PointerOutOfBounds(None)),
+            TapePolicy::GrowBoth if target < 0 => {
+                let grown = grown(len, len + target.unsigned_abs(), self.max_cells)? - len;
+                data.splice(0..0, std::iter::repeat_with(T::default).take(grown));
+                self.cursor += grown;
+                self.origin += grown;
+                Ok((target + grown as isize) as usize)
+            }
+            _ => {
+                let index = target as usize;
+                if index >= len {
+                    data.resize_with(grown(len, index + 1, self.max_cells)?, T::default);
+                }
+                Ok(index)
+            }
+        }
+    }
+
+    /// The cell at `index`, allocating its page if needed.
+    fn slot_mut(&mut self, index: usize) -> Result<&mut T, BfError> {
+        match &mut self.cells {
+            Cells::Dense(data) => Ok(&mut data[index]),
+            Cells::Paged(pages) => {
+                let page = pages.entry(index / PAGE_SIZE).or_insert_with(|| {
+                    std::iter::repeat_with(T::default).take(PAGE_SIZE).collect()
+                });
+                Ok(&mut page[index % PAGE_SIZE])
+            }
+        }
+    }
+
+    /// Put the cursor on an existing cell.
+    pub(crate) fn seek(&mut self, index: usize) {
+        debug_assert!(index < self.data().len());
+        self.cursor = index;
+    }
+
+    /// Pointer to and length of the cells, for code that works on them directly.
+    ///
+    /// # Panics
+    ///
+    /// If the tape is paged.
+    pub(crate) fn raw_parts(&mut self) -> (*mut T, usize) {
+        match &mut self.cells {
+            Cells::Dense(data) => (data.as_mut_ptr(), data.len()),
+            Cells::Paged(_) => panic!("a paged tape has no contiguous cells"),
+        }
+    }
+
+    /// A new tape of `D` cells following `policy`, paged if this one is.
+    pub(crate) fn cleared<D: Default + Clone>(&self, policy: TapePolicy) -> Tape<D> {
+        let mut tape = match &self.cells {
+            Cells::Dense(data) => Tape::with_policy(vec![D::default(); data.len()], policy),
+            Cells::Paged(_) => Tape::paged(policy),
+        };
+        tape.set_max_cells(self.max_cells);
+        tape
+    }
+
+    /// Stop the tape growing past `max` cells. Growing further fails with
+    /// `LimitExceeded(Limit::Cells, _)`, leaving the cursor where it was.
+    ///
+    /// A growing tape already holding more cells than that is cut back to
+    /// `max`, or to the cursor's cell if that's further. Fixed size and
+    /// wrapping tapes are allocated up front and never grow.
+    ///
+    /// A paged tape counts every cell between the furthest positions the
+    /// cursor has reached, allocated or not.
+    pub fn set_max_cells(&mut self, max: Option<usize>) {
+        self.max_cells = max;
+        if let (Some(max), Cells::Dense(data)) = (max, &mut self.cells) {
+            if let TapePolicy::GrowRight | TapePolicy::GrowBoth = self.policy {
+                data.truncate(max.max(self.cursor + 1));
+            }
+        }
+    }
+
+    /// What the tape does at its edges.
+    pub fn policy(&self) -> TapePolicy {
+        self.policy
+    }
+
+    /// Whether the cells are kept in pages rather than one block.
+    pub fn is_paged(&self) -> bool {
+        matches!(self.cells, Cells::Paged(_))
+    }
+
+    /// Number of cells memory has been allocated for.
+    pub fn allocated(&self) -> usize {
+        match &self.cells {
+            Cells::Dense(data) => data.len(),
+            Cells::Paged(pages) => pages.len() * PAGE_SIZE,
+        }
+    }
+
+    /// Index of the cursor in `data`.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Position of the cursor relative to the cell the tape started at,
+    /// negative once it has moved left of it.
+    pub fn position(&self) -> isize {
+        self.cursor.wrapping_sub(self.origin) as isize
+    }
+
+    /// The cell `position` cells from the one the tape started at, without growing the tape.
+    pub fn get(&self, position: isize) -> Option<&T> {
+        let index = self.origin.checked_add_signed(position)?;
+        match &self.cells {
+            Cells::Dense(data) => data.get(index),
+            Cells::Paged(_) if position < 0 && self.policy != TapePolicy::GrowBoth => None,
+            Cells::Paged(pages) => match self.policy {
+                TapePolicy::Wrap(len) | TapePolicy::Fixed(len) if position >= len as isize => None,
+                _ => Some(
+                    pages
+                        .get(&(index / PAGE_SIZE))
+                        .map_or(&self.zero, |page| &page[index % PAGE_SIZE]),
+                ),
+            },
+        }
+    }
+
+    /// The first and last positions that hold anything but zero cells that
+    /// were never written: the cells allocated on a dense tape, every cell of a
+    /// fixed size or wrapping one, and those the cursor has reached on others.
+    pub fn extent(&self) -> (isize, isize) {
+        match (&self.cells, self.policy) {
+            (Cells::Dense(data), _) => (
+                -(self.origin as isize),
+                (data.len() - self.origin) as isize - 1,
+            ),
+            (Cells::Paged(_), TapePolicy::Wrap(len) | TapePolicy::Fixed(len)) => {
+                (0, len as isize - 1)
+            }
+            (Cells::Paged(_), _) => self.reached,
+        }
+    }
+
+    /// All cells currently allocated.
+    ///
+    /// # Panics
+    ///
+    /// If the tape is paged, use `get` or `extent` instead.
+    pub fn data(&self) -> &[T] {
+        match &self.cells {
+            Cells::Dense(data) => data,
+            Cells::Paged(_) => panic!("a paged tape has no contiguous cells, use `get`"),
+        }
+    }
+
+    /// The cell under the cursor.
+    pub fn cell(&self) -> &T {
+        match &self.cells {
+            Cells::Dense(data) => &data[self.cursor],
+            Cells::Paged(pages) => pages
+                .get(&(self.cursor / PAGE_SIZE))
+                .map_or(&self.zero, |page| &page[self.cursor % PAGE_SIZE]),
+        }
+    }
+
+    /// The cell under the cursor, to write to.
+    pub fn cell_mut(&mut self) -> Result<&mut T, BfError> {
+        self.slot_mut(self.cursor)
+    }
+}
+
+/// Panic if `policy` leaves a tape without a cell for the cursor to point at.
+fn assert_sized(policy: TapePolicy) {
+    assert!(
+        !matches!(policy, TapePolicy::Wrap(0) | TapePolicy::Fixed(0)),
+        "a {:?} tape has no cells",
+        policy
+    );
+}
+
+/// Length to grow a tape of `len` cells to so it holds at least `needed`, at most `max`.
+fn grown(len: usize, needed: usize, max: Option<usize>) -> Result<usize, BfError> {
+    let len = (len * 2).max(needed);
+    match max {
+        Some(max) if needed > max => Err(BfError::LimitExceeded(Limit::Cells, None)),
+        Some(max) => Ok(len.min(max)),
+        None => Ok(len),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tape_move_right() {
+        let mut tape = Tape::new(vec![0, 0]);
+        tape.mv_right().unwrap();
+        assert_eq!(tape.cursor, 1);
+    }
+
+    #[test]
+    fn tape_move_left() {
+        let mut tape = Tape::new(vec![0, 0]);
+        tape.mv_right().unwrap();
+        tape.mv_left().unwrap();
+        assert_eq!(tape.cursor, 0);
+        assert!(tape.mv_left().is_err());
+    }
+
+    #[test]
+    fn tape_empty() {
+        let mut tape = Tape::<u8>::new(vec![]);
+        assert_eq!(*tape.cell(), 0);
+        tape.mv_right().unwrap();
+        assert_eq!(*tape.cell(), 0);
+    }
+
+    #[test]
+    fn tape_move_by() {
+        let mut tape = Tape::new(vec![0, 0]);
+        tape.mv(5).unwrap();
+        assert_eq!(tape.cursor, 5);
+        assert!(tape.data().len() > 5);
+        tape.mv(-5).unwrap();
+        assert_eq!(tape.cursor, 0);
+        assert!(tape.mv(-1).is_err());
+    }
+
+    #[test]
+    fn tape_offset() {
+        let mut tape = Tape::new(vec![0, 0]);
+        *tape.offset_mut(3).unwrap() = 7;
+        assert_eq!(tape.cursor, 0);
+        assert_eq!(tape.data()[3], 7);
+        assert!(tape.offset_mut(-1).is_err());
+    }
+
+    #[test]
+    fn tape_scan() {
+        let mut tape = Tape::new(vec![1, 1, 0, 1]);
+        tape.scan_right().unwrap();
+        assert_eq!(tape.cursor, 2);
+        tape.mv_right().unwrap();
+        tape.scan_right().unwrap();
+        assert_eq!(tape.cursor, 4);
+        tape.mv(-2).unwrap();
+        tape.scan_left().unwrap();
+        assert_eq!(tape.cursor, 2);
+        tape.mv(-1).unwrap();
+        assert!(tape.scan_left().is_err());
+    }
+
+    #[test]
+    fn tape_cell() {
+        let mut tape = Tape::new(vec![0, 0]);
+        tape.mv_right().unwrap();
+        assert_eq!(*tape.cell(), 0);
+    }
+
+    #[test]
+    fn tape_grow_both() {
+        let mut tape = Tape::with_policy(vec![1, 2], TapePolicy::GrowBoth);
+        tape.mv(-3).unwrap();
+        assert_eq!(tape.position(), -3);
+        assert_eq!(*tape.cell(), 0);
+        *tape.cell_mut().unwrap() = 3;
+        tape.mv(3).unwrap();
+        assert_eq!(*tape.cell(), 1);
+        assert_eq!(tape.position(), 0);
+        assert_eq!(tape.data()[tape.cursor() - 3], 3);
+        tape.scan_left().unwrap();
+        assert_eq!(tape.position(), -1);
+    }
+
+    #[test]
+    fn tape_wrap() {
+        let mut tape = Tape::with_policy(vec![], TapePolicy::Wrap(3));
+        assert_eq!(tape.data().len(), 3);
+        tape.mv_left().unwrap();
+        assert_eq!(tape.cursor(), 2);
+        tape.mv(4).unwrap();
+        assert_eq!(tape.cursor(), 0);
+        *tape.offset_mut(-1).unwrap() = 5;
+        assert_eq!(tape.data(), &[0, 0, 5]);
+
+        let mut tape = Tape::with_policy(vec![0, 1, 1], TapePolicy::Wrap(3));
+        tape.mv_right().unwrap();
+        tape.scan_right().unwrap();
+        assert_eq!(tape.cursor(), 0);
+        tape.mv_left().unwrap();
+        tape.scan_left().unwrap();
+        assert_eq!(tape.cursor(), 0);
+    }
+
+    #[test]
+    fn tape_fixed() {
+        let mut tape = Tape::with_policy(vec![1, 1, 1, 1], TapePolicy::Fixed(2));
+        assert_eq!(tape.data(), &[1, 1]);
+        tape.mv_right().unwrap();
+        assert!(tape.mv_right().is_err());
+        assert!(tape.offset_mut(-2).is_err());
+        assert!(tape.scan_right().is_err());
+        assert!(tape.scan_left().is_err());
+        assert_eq!(tape.data().len(), 2);
+    }
+
+    #[test]
+    fn tape_paged() {
+        let mut tape = Tape::<u8>::paged(TapePolicy::GrowRight);
+        assert_eq!(*tape.cell(), 0);
+        assert_eq!(tape.allocated(), 0);
+        tape.mv(50_000_000).unwrap();
+        assert_eq!(*tape.cell(), 0);
+        *tape.cell_mut().unwrap() = 7;
+        *tape.offset_mut(1).unwrap() = 8;
+        assert_eq!(tape.allocated(), PAGE_SIZE);
+        assert_eq!(tape.get(50_000_001), Some(&8));
+        assert_eq!(tape.get(0), Some(&0));
+        tape.scan_right().unwrap();
+        assert_eq!(tape.position(), 50_000_002);
+        tape.mv(-50_000_002).unwrap();
+        assert!(tape.mv_left().is_err());
+        assert_eq!(tape.allocated(), PAGE_SIZE);
+    }
+
+    #[test]
+    #[should_panic(expected = "a Wrap(0) tape has no cells")]
+    fn tape_paged_no_cells() {
+        Tape::<u8>::paged(TapePolicy::Wrap(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "a Fixed(0) tape has no cells")]
+    fn tape_no_cells() {
+        Tape::<u8>::with_policy(Vec::new(), TapePolicy::Fixed(0));
+    }
+
+    #[test]
+    fn tape_paged_policies() {
+        let mut tape = Tape::<u8>::paged(TapePolicy::GrowBoth);
+        tape.mv(-10_000).unwrap();
+        *tape.cell_mut().unwrap() = 1;
+        assert_eq!(tape.position(), -10_000);
+        assert_eq!(tape.get(-10_000), Some(&1));
+
+        let mut tape = Tape::<u8>::paged(TapePolicy::Wrap(3));
+        tape.mv_left().unwrap();
+        assert_eq!(tape.position(), 2);
+        assert_eq!(tape.get(3), None);
+
+        let mut tape = Tape::<u8>::paged(TapePolicy::Fixed(3));
+        tape.mv(2).unwrap();
+        assert!(tape.mv_right().is_err());
+        assert_eq!(tape.get(-1), None);
+    }
+
+    #[test]
+    fn tape_max_cells() {
+        let mut tape = Tape::<u8>::new(vec![0; 4]);
+        tape.set_max_cells(Some(6));
+        tape.mv(5).unwrap();
+        assert_eq!(tape.allocated(), 6);
+        assert!(matches!(
+            tape.mv_right(),
+            Err(BfError::LimitExceeded(Limit::Cells, None))
+        ));
+
+        let mut tape = Tape::<u8>::paged(TapePolicy::GrowRight);
+        tape.set_max_cells(Some(PAGE_SIZE));
+        *tape.cell_mut().unwrap() = 1;
+        tape.mv(PAGE_SIZE as isize - 1).unwrap();
+        assert!(tape.mv_right().is_err());
+
+        // Capped by cells reached, as for a dense tape, not whole pages
+        let mut tape = Tape::<u8>::paged(TapePolicy::GrowRight);
+        tape.set_max_cells(Some(10));
+        *tape.cell_mut().unwrap() = 1;
+        tape.mv(9).unwrap();
+        assert!(matches!(
+            tape.mv_right(),
+            Err(BfError::LimitExceeded(Limit::Cells, None))
+        ));
+        assert_eq!(tape.position(), 9);
+        assert_eq!(tape.allocated(), PAGE_SIZE);
+
+        let mut tape = Tape::<u8>::paged(TapePolicy::GrowBoth);
+        tape.set_max_cells(Some(10));
+        tape.mv(-5).unwrap();
+        tape.mv(9).unwrap();
+        assert!(tape.mv_right().is_err());
+        assert!(tape.mv(-9).is_ok());
+        assert!(tape.mv_left().is_err());
+
+        // Cut back to the cap, so the tape can't reach past it without growing
+        let mut tape = Tape::<u8>::new(vec![0; 512]);
+        tape.set_max_cells(Some(10));
+        assert_eq!(tape.allocated(), 10);
+        assert!(tape.mv(9).is_ok());
+        assert!(tape.mv_right().is_err());
+    }
+
+    #[test]
+    fn tape_dump() {
+        let mut tape = Tape::<u8>::new(vec![0, 255, 3]);
+        tape.mv_right().unwrap();
+        let mut out = Vec::new();
+        tape.dump(&mut out, 1).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "cell   0   1   2\nhex   00  ff  03\ndec    0 255   3\n           ^\n"
+        );
+
+        // Cut down to the cells the tape has, without going round them all
+        let mut out = Vec::new();
+        tape.dump(&mut out, usize::MAX).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("cell   0   1   2\n"));
+
+        let mut tape = Tape::<u8>::paged(TapePolicy::GrowBoth);
+        tape.mv(-2).unwrap();
+        tape.mv(3).unwrap();
+        let mut out = Vec::new();
+        tape.dump(&mut out, usize::MAX).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("cell -2 -1  0  1\n"));
+    }
+}
@@ -0,0 +1,157 @@
+//! Lowers programs to a WebAssembly text module.
+//!
+//! The module imports `env.read_byte: () -> i32` and `env.write_byte: (i32) -> ()`,
+//! exports its linear memory as `memory` and the program as `run`. The tape
+//! starts at address 0 with 8-bit wrapping cells, memory grows a page at a time
+//! when the pointer runs off the end, and moving left of the first cell traps.
+//! There is nowhere to send debug dumps, so `#` is left out.
+use std::fmt::Write;
+
+use crate::ir::{self, Ir};
+use crate::{BfError, Operation};
+
+const HEADER: &str = r#"(module
+  (import "env" "read_byte" (func $read_byte (result i32)))
+  (import "env" "write_byte" (func $write_byte (param i32)))
+  (memory (export "memory") 1)
+
+  ;; Make sure the cell at $i exists, growing memory if needed
+  (func $ensure (param $i i32) (result i32)
+    (if (i32.lt_s (local.get $i) (i32.const 0))
+      (then unreachable))
+    (block $done
+      (loop $grow
+        (br_if $done
+          (i32.lt_u (local.get $i) (i32.mul (memory.size) (i32.const 65536))))
+        (if (i32.eq (memory.grow (i32.const 1)) (i32.const -1))
+          (then unreachable))
+        (br $grow)))
+    (local.get $i))
+
+  (func (export "run")
+    (local $p i32)
+    (local $t i32)
+"#;
+
+/// Translate `ops` into the source of a WebAssembly text module.
+///
+/// Fails if the brackets are unbalanced.
+pub fn compile(ops: &[Operation]) -> Result<String, BfError> {
+    let ir = ir::optimize(ir::lower(ops)?);
+    let mut out = HEADER.to_string();
+    let mut labels = 0;
+    block(&mut out, &ir, 2, &mut labels);
+    out.push_str("  ))\n");
+    Ok(out)
+}
+
+/// Emit `ir` indented `depth` levels, `labels` numbers the loops.
+fn block(out: &mut String, ir: &[(Ir, usize)], depth: usize, labels: &mut usize) {
+    let indent = "  ".repeat(depth);
+    for (instr, _) in ir {
+        out.push_str(&indent);
+        match instr {
+            Ir::Add(delta) => writeln!(
+                out,
+                "(i32.store8 (local.get $p) (i32.add (i32.load8_u (local.get $p)) (i32.const {})))",
+                delta
+            ),
+            Ir::Move(n) => writeln!(out, "{}", mv(*n)),
+            Ir::AddAt { offset, delta } => writeln!(
+                out,
+                "{}\n{}(i32.store8 (local.get $t) (i32.add (i32.load8_u (local.get $t)) (i32.const {})))",
+                target(*offset),
+                indent,
+                delta
+            ),
+            Ir::Check { low, high } => {
+                writeln!(out, "{}\n{}{}", target(*low), indent, target(*high))
+            }
+            Ir::Output => writeln!(out, "(call $write_byte (i32.load8_u (local.get $p)))"),
+            Ir::Input => writeln!(out, "(i32.store8 (local.get $p) (call $read_byte))"),
+            Ir::DebugDump => writeln!(out, ";; #"),
+            Ir::Loop(body) => {
+                let label = *labels;
+                *labels += 1;
+                writeln!(
+                    out,
+                    "(block $end{0}\n{1}  (loop $loop{0}\n{1}    (br_if $end{0} (i32.eqz (i32.load8_u (local.get $p))))",
+                    label, indent
+                )
+                .unwrap();
+                block(out, body, depth + 2, labels);
+                writeln!(out, "{}    (br $loop{})))", indent, label)
+            }
+            Ir::SetZero => writeln!(out, "(i32.store8 (local.get $p) (i32.const 0))"),
+            Ir::MulAdd { offset, factor } => writeln!(
+                out,
+                "(if (i32.load8_u (local.get $p))\n{0}  (then\n{0}    {1}\n{0}    (i32.store8 (local.get $t) (i32.add (i32.load8_u (local.get $t)) (i32.mul (i32.load8_u (local.get $p)) (i32.const {2}))))))",
+                indent,
+                target(*offset),
+                factor
+            ),
+            Ir::ScanRight | Ir::ScanLeft => {
+                let label = *labels;
+                *labels += 1;
+                let step = if *instr == Ir::ScanRight { 1 } else { -1 };
+                writeln!(
+                    out,
+                    "(block $end{0}\n{1}  (loop $loop{0}\n{1}    (br_if $end{0} (i32.eqz (i32.load8_u (local.get $p))))\n{1}    {2}\n{1}    (br $loop{0})))",
+                    label,
+                    indent,
+                    mv(step)
+                )
+            }
+        }
+        .unwrap();
+    }
+}
+
+fn mv(n: isize) -> String {
+    format!(
+        "(local.set $p (call $ensure (i32.add (local.get $p) (i32.const {}))))",
+        n
+    )
+}
+
+/// Point `$t` at the cell `offset` away from the pointer.
+fn target(offset: isize) -> String {
+    format!(
+        "(local.set $t (call $ensure (i32.add (local.get $p) (i32.const {}))))",
+        offset
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn compile_src(src: &str) -> String {
+        compile(&parse(src.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn wat_empty() {
+        let wat = compile_src("");
+        assert!(wat.starts_with("(module\n"));
+        assert!(wat.ends_with("    (local $t i32)\n  ))\n"));
+    }
+
+    #[test]
+    fn wat_loops_are_labelled() {
+        let wat = compile_src("[>[.-]<]");
+        assert!(wat.contains("(block $end0\n"));
+        assert!(wat.contains("(br $loop0)))\n"));
+        assert!(wat.contains("(block $end1\n"));
+        assert!(wat.contains("(br $loop1)))\n"));
+    }
+
+    #[test]
+    fn wat_unmatched() {
+        assert!(matches!(
+            compile(&[Operation::JumpForward]),
+            Err(BfError::UnmatchedOpen(0))
+        ));
+    }
+}
@@ -0,0 +1,212 @@
+//! Compiles generated C with the system compiler and checks it against `Program::run`.
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use brainfuck::c::{self, COptions};
+use brainfuck::{
+    ir, parse, parse_with, Cell, EofPolicy, InputMode, Operation, OutputMode, ParseOptions, Program,
+};
+
+/// Exit status of the command line, and the generated C, for a program that fails while running.
+const RUNTIME_ERROR: i32 = 3;
+
+/// Build `ops` with `cc`, or `None` if there is no C compiler.
+fn build(name: &str, ops: &[Operation], options: &COptions) -> Option<PathBuf> {
+    let code = c::compile(&ir::optimize(ir::lower(ops).unwrap()), options);
+
+    let dir = std::env::temp_dir().join(format!("bf-c-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join(format!("{}.c", name));
+    let binary = dir.join(name);
+    std::fs::write(&source, code).unwrap();
+
+    let status = Command::new("cc")
+        .arg("-O1")
+        .arg("-o")
+        .arg(&binary)
+        .arg(&source)
+        .status()
+        .ok()?;
+    assert!(status.success(), "cc failed on {}", source.display());
+    Some(binary)
+}
+
+/// Run the compiled program, returning its exit status, stdout and stderr.
+fn execute(binary: &PathBuf, input: &str) -> (Option<i32>, Vec<u8>, Vec<u8>) {
+    let mut child = Command::new(binary)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (output.status.code(), output.stdout, output.stderr)
+}
+
+fn remove(binary: PathBuf) {
+    std::fs::remove_file(binary.with_extension("c")).unwrap();
+    std::fs::remove_file(binary).unwrap();
+}
+
+fn assert_same(name: &str, src: &str, input: &str) {
+    assert_same_with(name, src, input, COptions::default());
+}
+
+fn assert_same_with(name: &str, src: &str, input: &str, options: COptions) {
+    assert_same_cells::<u8>(name, src, input, options);
+}
+
+/// Like `assert_same_with`, for C programs whose `cell_type` matches `C`.
+fn assert_same_cells<C: Cell>(name: &str, src: &str, input: &str, options: COptions) {
+    let ops = parse(src.as_bytes()).unwrap();
+    let binary = match build(name, &ops, &options) {
+        Some(binary) => binary,
+        None => return eprintln!("skipping {}, no C compiler", name),
+    };
+
+    let mut program = Program::with_io(ops, input.as_bytes(), Vec::new())
+        .with_cell::<C>()
+        .with_eof(options.eof)
+        .with_input_mode(options.input_mode)
+        .with_output_mode(options.output_mode);
+    let status = match program.run() {
+        Ok(()) => 0,
+        Err(_) => RUNTIME_ERROR,
+    };
+
+    let (code, stdout, _) = execute(&binary, input);
+    assert_eq!(
+        (code, stdout),
+        (Some(status), program.into_output()),
+        "{}",
+        src
+    );
+    remove(binary);
+}
+
+#[test]
+fn c_hello_world() {
+    assert_same(
+        "hello",
+        "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+        "",
+    );
+}
+
+#[test]
+fn c_wrapping() {
+    assert_same("wrap", "-.+.>+[-].", "");
+}
+
+#[test]
+fn c_output_modes() {
+    let src = "-.+.>++++++++[<++++++++++++++++++++++++>-]<+.";
+    for (name, output_mode) in [
+        ("raw", OutputMode::Raw),
+        ("decimal", OutputMode::Decimal),
+        ("hex", OutputMode::Hex),
+        ("latin1", OutputMode::Latin1),
+    ] {
+        let options = COptions {
+            output_mode,
+            ..COptions::default()
+        };
+        assert_same_with(name, src, "", options);
+    }
+}
+
+#[test]
+fn c_wide_cells() {
+    // Wraps at the cell's maximum, then multiplies up to 256 and 65536
+    let src = "-.+.++++++++[>++++++++<-]>[>++++<-]>.[>>++++++++[<++++++++++++++++++++++++++++++++>-]<<-]>.";
+    for (name, cell_type) in [("u16", "uint16_t"), ("u32", "uint32_t")] {
+        let options = || COptions {
+            cell_type: cell_type.to_string(),
+            output_mode: OutputMode::Decimal,
+            ..COptions::default()
+        };
+        match name {
+            "u16" => assert_same_cells::<u16>(name, src, "", options()),
+            _ => assert_same_cells::<u32>(name, src, "", options()),
+        }
+    }
+}
+
+#[test]
+fn c_debug_dump() {
+    let src = "+++>>>->#";
+    let options = ParseOptions { debug_hash: true };
+    let ops = parse_with(src.as_bytes(), options).unwrap();
+    let binary = match build("dump", &ops, &COptions::default()) {
+        Some(binary) => binary,
+        None => return eprintln!("skipping dump, no C compiler"),
+    };
+
+    let cli = Command::new(env!("CARGO_BIN_EXE_brainfuck"))
+        .args(["--debug-hash", "-e", src])
+        .output()
+        .unwrap();
+    let (_, _, stderr) = execute(&binary, "");
+    assert_eq!(String::from_utf8(stderr), String::from_utf8(cli.stderr));
+    remove(binary);
+}
+
+#[test]
+fn c_tape_growth() {
+    assert_same("grow", &format!("{}+.[<]", "+>".repeat(2000)), "");
+}
+
+#[test]
+fn c_input() {
+    assert_same("input", ",>,[<+>-]<.", "\x20\x21");
+    let options = COptions {
+        eof: EofPolicy::Zero,
+        ..COptions::default()
+    };
+    assert_same_with("cat", ",[.,]", "hello\n", options);
+}
+
+#[test]
+fn c_decimal_input() {
+    let options = || COptions {
+        input_mode: InputMode::DecimalLine,
+        ..COptions::default()
+    };
+    assert_same_with("decimal", ",>,[<+>-]<.", "30\n35\n", options());
+    assert_same_with("bad_decimal", ",.", "nope\n", options());
+}
+
+#[test]
+fn c_underflow() {
+    assert_same("underflow", "+.<.", "");
+}
+
+#[test]
+fn c_eof() {
+    let src = "+++++,.,.";
+    for (name, eof) in [
+        ("eof_unchanged", EofPolicy::Unchanged),
+        ("eof_zero", EofPolicy::Zero),
+        ("eof_minus_one", EofPolicy::MinusOne),
+    ] {
+        let options = COptions {
+            eof,
+            ..COptions::default()
+        };
+        assert_same_with(name, src, "A", options);
+    }
+
+    let options = COptions {
+        eof: EofPolicy::Zero,
+        input_mode: InputMode::DecimalLine,
+        ..COptions::default()
+    };
+    assert_same_with("eof_decimal", src, "65\n", options);
+}
@@ -0,0 +1,174 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Run the binary with `args`, writing `stdin` to it.
+fn brainfuck(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_brainfuck"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Programs that don't read stdin may have exited already
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn runs_inline_and_stdin_programs() {
+    let output = brainfuck(&["-e", "++++++++[>++++++++<-]>+."], "");
+    assert_eq!(stdout(&output), "A");
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = brainfuck(&["run", "--cell-size", "16", "-"], "-.");
+    assert_eq!(output.stdout, [0xff]);
+}
+
+#[test]
+fn reads_input_from_a_file() {
+    let output = brainfuck(
+        &["--input", "tests/programs/rot13.bf", "-e", ",.,."],
+        "not read",
+    );
+    assert_eq!(stdout(&output), "-,");
+}
+
+#[test]
+fn checks_programs() {
+    let output = brainfuck(&["check", "tests/programs/rot13.bf"], "");
+    assert!(stdout(&output).starts_with("tests/programs/rot13.bf: "));
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = brainfuck(&["check", "-e", "[[]\n]]"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("error: unmatched `]`\n --> <-e>:2:2\n"));
+}
+
+#[test]
+fn profiles_programs() {
+    let output = brainfuck(&["profile", "-e", "++[>+<-]"], "");
+    assert!(stderr(&output).starts_with("13 steps\nline steps | source\n   1    13 | ++[>+<-]\n"));
+}
+
+#[test]
+fn exit_codes() {
+    let output = brainfuck(&["-e", "<"], "");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        stderr(&output),
+        "error: <-e>:1:1: pointer moved off the tape at instruction 0\n"
+    );
+
+    let output = brainfuck(&["--jit", "--max-steps", "100", "-e", "+[]"], "");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        stderr(&output),
+        "error: <-e>:1:2: step limit exceeded at instruction 1\n"
+    );
+
+    assert_eq!(brainfuck(&["--bogus"], "").status.code(), Some(2));
+    assert_eq!(brainfuck(&[], "").status.code(), Some(2));
+    assert_eq!(brainfuck(&["missing.bf"], "").status.code(), Some(4));
+    let output = brainfuck(&["--input", "missing.txt", "-e", ","], "");
+    assert_eq!(output.status.code(), Some(4));
+    assert!(stderr(&output).starts_with("error: missing.txt: "));
+    let output = brainfuck(&["--max-cells", "0", "-e", "+"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("error: --max-cells must be at least 1\n"));
+
+    let output = brainfuck(&["--max-cells", "10", "-e", ">>>>>>>>>>>>>>>>>>>>+"], "");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        stderr(&output),
+        "error: <-e>:1:10: memory limit exceeded at instruction 9\n"
+    );
+
+    let output = brainfuck(&["--help"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("exit status:"));
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn jit_errors_match_the_interpreter() {
+    let cases: [&[&str]; 4] = [
+        &["-e", "<+>"],
+        &["-e", "<>"],
+        &["-e", "+[<>-]"],
+        &["--tape", "fixed:3", "-e", ">>><"],
+    ];
+    for args in cases {
+        let interpreted = brainfuck(args, "");
+        let jitted = brainfuck(&[&["--jit"], args].concat(), "");
+        assert_eq!(interpreted.status.code(), Some(3), "{:?}", args);
+        assert_eq!(jitted.status.code(), Some(3), "{:?}", args);
+        assert_eq!(stderr(&interpreted), stderr(&jitted), "{:?}", args);
+    }
+    assert_eq!(
+        stderr(&brainfuck(&["--jit", "-e", "<+>"], "")),
+        "error: <-e>:1:1: pointer moved off the tape at instruction 0\n"
+    );
+}
+
+#[test]
+fn formats_programs() {
+    let output = brainfuck(&["fmt", "-"], "+++[>+<-] done");
+    assert_eq!(stdout(&output), "+++\n[\n  > + < -\n] done\n");
+    let again = brainfuck(&["fmt", "-"], &stdout(&output));
+    assert_eq!(stdout(&again), stdout(&output));
+}
+
+#[test]
+fn minifies_programs() {
+    let output = brainfuck(&["minify", "-e", "[ comment ] +- ++ <> [-] [.] >."], "");
+    assert_eq!(stdout(&output), "++[-]>.");
+}
+
+#[test]
+fn lints_programs() {
+    let output = brainfuck(&["lint", "-e", "+[-][.]"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).starts_with("warning: this loop never runs (dead_loop)\n --> <-e>:1:5\n")
+    );
+    assert!(stderr(&output).ends_with("\n<-e>: 1 warning\n"));
+
+    let output = brainfuck(&["lint", "--allow", "dead_loop", "-e", "+[-][.]"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        brainfuck(&["lint", "--allow", "bogus", "-e", "+"], "")
+            .status
+            .code(),
+        Some(2)
+    );
+}
+
+#[test]
+fn compiles_to_wat_with_byte_cells_only() {
+    let output = brainfuck(&["compile", "--target", "wat", "-e", "+."], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).starts_with("(module"));
+
+    for args in [
+        ["--cell-size", "16"],
+        ["--eof", "0"],
+        ["--tape-size", "100"],
+        ["--cell-type", "int"],
+        ["--input-mode", "decimal"],
+    ] {
+        let output = brainfuck(
+            &[&["compile"], &args[..], &["--target", "wat", "-e", "+"]].concat(),
+            "",
+        );
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+    }
+}
@@ -0,0 +1,216 @@
+use std::time::{Duration, Instant};
+
+use brainfuck::{
+    parse, BfError, EofPolicy, InputMode, Limit, Limits, Operation, Program, TapePolicy,
+};
+
+fn load(src: &str) -> Program {
+    Program::new(parse(src.as_bytes()).unwrap())
+}
+
+#[test]
+fn runs_parsed_source() {
+    let mut prog = load("++>+++[<+>-]");
+    prog.run().unwrap();
+    assert_eq!(&prog.memory().data()[..2], &[5, 0]);
+    assert_eq!(prog.memory().cursor(), 1);
+}
+
+#[test]
+fn steps_through_ops() {
+    let mut prog = load("+>");
+    assert_eq!(*prog.ops().cell(), Operation::Increment);
+    prog.step().unwrap();
+    assert_eq!(*prog.memory().cell(), 1);
+    assert_eq!(*prog.ops().cell(), Operation::MoveRight);
+    prog.step().unwrap();
+    assert_eq!(prog.memory().cursor(), 1);
+    assert_eq!(*prog.ops().cell(), Operation::NoOp);
+}
+
+#[test]
+fn memory_can_be_seeded() {
+    let mut prog = load("[->+<]");
+    *prog.memory_mut().cell_mut().unwrap() = 7;
+    prog.run().unwrap();
+    assert_eq!(&prog.memory().data()[..2], &[0, 7]);
+}
+
+#[test]
+fn writes_to_caller_output() {
+    let ops = parse("++++++++[>++++++++<-]>+.+.".as_bytes()).unwrap();
+    let mut out = Vec::new();
+    let mut prog = Program::with_io(ops, std::io::empty(), &mut out);
+    prog.run().unwrap();
+    assert_eq!(out, b"AB");
+}
+
+#[test]
+fn reads_from_caller_input() {
+    let ops = parse(",>,[<+>-]<.".as_bytes()).unwrap();
+    let mut prog = Program::with_io(ops, "30\n35\n".as_bytes(), Vec::new())
+        .with_input_mode(InputMode::DecimalLine);
+    prog.run().unwrap();
+    assert_eq!(prog.into_output(), b"A");
+}
+
+#[test]
+fn rejects_unbalanced_source() {
+    assert!(matches!(
+        parse("[[]".as_bytes()),
+        Err(BfError::UnmatchedOpen(0))
+    ));
+    assert!(matches!(
+        parse("[]]".as_bytes()),
+        Err(BfError::UnmatchedClose(2))
+    ));
+}
+
+#[test]
+fn reports_pointer_out_of_bounds() {
+    let mut prog = load("+<");
+    assert!(matches!(
+        prog.run(),
+        Err(BfError::PointerOutOfBounds(Some(1)))
+    ));
+}
+
+#[test]
+fn tape_policies() {
+    let src = "+<+<<+";
+    let load_with = |policy| load(src).with_tape_policy(policy);
+
+    let mut prog = load_with(TapePolicy::GrowBoth);
+    prog.run().unwrap();
+    let memory = prog.memory();
+    assert_eq!(memory.position(), -3);
+    assert_eq!(memory.data()[memory.cursor()..][..4], [1, 0, 1, 1]);
+
+    let mut prog = load_with(TapePolicy::Wrap(4));
+    prog.run().unwrap();
+    assert_eq!(prog.memory().data(), [1, 1, 0, 1]);
+
+    let mut prog = load_with(TapePolicy::CLASSIC);
+    assert!(matches!(
+        prog.run(),
+        Err(BfError::PointerOutOfBounds(Some(1)))
+    ));
+}
+
+#[test]
+fn ir_matches_naive_run() {
+    let src = "++++++++++[>+++++++>++++++++++>+++>+<<<<-]>++.>+.+++++++..+++.>++.<<+++++++++++++++.>.+++.------.--------.>+.>.";
+    let ops = parse(src.as_bytes()).unwrap();
+
+    let mut naive = Program::with_io(ops.clone(), std::io::empty(), Vec::new());
+    naive.run().unwrap();
+    let mut folded = Program::with_io(ops, std::io::empty(), Vec::new());
+    folded.run_ir().unwrap();
+
+    assert_eq!(naive.memory().cursor(), folded.memory().cursor());
+    assert_eq!(naive.into_output(), folded.into_output());
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+#[test]
+fn jit_matches_naive_run() {
+    let src = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+    let ops = parse(src.as_bytes()).unwrap();
+
+    let mut naive = Program::with_io(ops.clone(), std::io::empty(), Vec::new());
+    naive.run().unwrap();
+    let mut jit = Program::with_io(ops, std::io::empty(), Vec::new());
+    jit.run_jit().unwrap();
+
+    assert_eq!(naive.memory().cursor(), jit.memory().cursor());
+    assert_eq!(naive.into_output(), jit.into_output());
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+#[test]
+fn jit_reads_input() {
+    let ops = parse(",>,[<+>-]<.".as_bytes()).unwrap();
+    let mut prog = Program::with_io(ops, &[0x20, 0x21][..], Vec::new());
+    prog.run_jit().unwrap();
+    assert_eq!(prog.into_output(), b"A");
+}
+
+#[test]
+fn runs_with_wide_cells() {
+    let ops = parse("-[>+<-]".as_bytes()).unwrap();
+    let mut prog = Program::with_io(ops, std::io::empty(), Vec::new()).with_cell::<u16>();
+    prog.run().unwrap();
+    assert_eq!(prog.memory().data()[1], u16::MAX);
+}
+
+/// Copies input to output a byte at a time, stopping at the end of input.
+const CAT: &str = ",[.,]";
+
+#[test]
+fn byte_input_is_default() {
+    let ops = parse(CAT.as_bytes()).unwrap();
+    let mut prog =
+        Program::with_io(ops, "hello, world\n".as_bytes(), Vec::new()).with_eof(EofPolicy::Zero);
+    prog.run().unwrap();
+    assert_eq!(prog.into_output(), b"hello, world\n");
+}
+
+#[test]
+fn counts_input_bytes() {
+    // wc -c, for inputs under 10 bytes
+    let src = ",[>+<[-],]>++++++++++++++++++++++++++++++++++++++++++++++++.";
+    let ops = parse(src.as_bytes()).unwrap();
+    let mut prog =
+        Program::with_io(ops, "abcdefg".as_bytes(), Vec::new()).with_eof(EofPolicy::Zero);
+    prog.run().unwrap();
+    assert_eq!(prog.into_output(), b"7");
+}
+
+#[test]
+fn runs_rot13() {
+    let ops = parse(include_str!("programs/rot13.bf").as_bytes()).unwrap();
+    let mut prog = Program::with_io(ops, "Hello, World!\n".as_bytes(), Vec::new());
+    prog.run().unwrap();
+    assert_eq!(prog.into_output(), b"Uryyb, Jbeyq!\n");
+}
+
+#[test]
+fn runs_squares() {
+    let ops = parse(include_str!("programs/squares.bf").as_bytes()).unwrap();
+    let mut prog = Program::with_io(ops, std::io::empty(), Vec::new());
+    prog.run().unwrap();
+    let expected: String = (0..=100).map(|n| format!("{}\n", n * n)).collect();
+    assert_eq!(String::from_utf8(prog.into_output()).unwrap(), expected);
+}
+
+#[test]
+fn paged_memory_stays_small() {
+    // Walk a million cells right and leave a marker there
+    let src = format!("{}+", ">".repeat(1_000_000));
+    let ops = parse(src.as_bytes()).unwrap();
+
+    let mut dense = Program::with_io(ops.clone(), std::io::empty(), std::io::sink());
+    dense.run_ir().unwrap();
+    assert!(dense.memory().allocated() > 1_000_000);
+
+    let mut paged = Program::with_io(ops, std::io::empty(), std::io::sink()).with_paged_memory();
+    paged.run_ir().unwrap();
+    assert!(paged.memory().allocated() < 10_000);
+    assert_eq!(paged.memory().get(1_000_000), Some(&1));
+    assert_eq!(paged.memory().position(), 1_000_000);
+}
+
+#[test]
+fn limits_stop_runaway_programs() {
+    let limits = Limits {
+        steps: Some(1_000_000),
+        deadline: Some(Instant::now() + Duration::from_secs(10)),
+        ..Limits::default()
+    };
+    let mut prog = load("+[>+<]").with_limits(limits);
+    let err = prog.run().unwrap_err();
+    assert!(matches!(err, BfError::LimitExceeded(Limit::Steps, Some(_))));
+    assert!(err
+        .to_string()
+        .starts_with("step limit exceeded at instruction "));
+}
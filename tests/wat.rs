@@ -0,0 +1,129 @@
+//! Checks the structure of generated WebAssembly text. Running it needs
+//! `wat2wasm` and `node`, so that test is ignored unless asked for with
+//! `cargo test -- --ignored`.
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use brainfuck::{parse, wat, Program};
+
+fn compile(src: &str) -> String {
+    wat::compile(&parse(src.as_bytes()).unwrap()).unwrap()
+}
+
+/// Check every paren in `wat` is closed, outside of strings and comments.
+fn assert_balanced(wat: &str) {
+    let mut depth = 0;
+    let mut in_string = false;
+    for line in wat.lines() {
+        let line = line.split(";;").next().unwrap();
+        for c in line.chars() {
+            match c {
+                '"' => in_string = !in_string,
+                '(' if !in_string => depth += 1,
+                ')' if !in_string => {
+                    depth -= 1;
+                    assert!(depth >= 0, "unexpected `)`:\n{}", wat);
+                    if depth == 0 {
+                        assert!(line.ends_with(')'), "trailing input after module:\n{}", wat);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    assert_eq!(depth, 0, "unclosed `(`:\n{}", wat);
+}
+
+const HELLO: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+#[test]
+fn wat_module_structure() {
+    let wat = compile(HELLO);
+    assert_balanced(&wat);
+    assert!(wat.starts_with("(module\n"));
+    assert!(wat.contains(r#"(import "env" "read_byte" (func $read_byte (result i32)))"#));
+    assert!(wat.contains(r#"(import "env" "write_byte" (func $write_byte (param i32)))"#));
+    assert!(wat.contains(r#"(memory (export "memory") 1)"#));
+    assert!(wat.contains(r#"(func (export "run")"#));
+}
+
+#[test]
+fn wat_every_loop_is_closed() {
+    let wat = compile("+[>+[>+[-]<-]<-][>]<[<],.");
+    assert_balanced(&wat);
+    let opened = wat.matches("(loop $loop").count();
+    assert_eq!(opened, wat.matches("(br $loop").count());
+    assert_eq!(opened, 4);
+}
+
+/// A directory that is removed again when dropped, even if the test fails.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Run `wat` with node, feeding `input` to `read_byte`.
+fn run_with_node(wat: &str, input: &[u8]) -> Vec<u8> {
+    let dir = TempDir::new("bf-wat");
+    let source = dir.0.join("program.wat");
+    let binary = dir.0.join("program.wasm");
+    std::fs::write(&source, wat).unwrap();
+
+    let status = Command::new("wat2wasm")
+        .arg(&source)
+        .arg("-o")
+        .arg(&binary)
+        .status()
+        .expect("wat2wasm should be installed");
+    assert!(status.success(), "wat2wasm rejected {}", source.display());
+
+    let script = format!(
+        "const input = Buffer.from(process.argv[1], 'hex'); let i = 0; const out = [];
+         WebAssembly.instantiate(require('fs').readFileSync({:?}), {{ env: {{
+             read_byte: () => i < input.length ? input[i++] : 0,
+             write_byte: (b) => out.push(b),
+         }} }}).then(({{ instance }}) => {{
+             instance.exports.run();
+             process.stdout.write(Buffer.from(out));
+         }});",
+        binary.to_str().unwrap()
+    );
+    let hex: String = input.iter().map(|b| format!("{:02x}", b)).collect();
+    let output = Command::new("node")
+        .arg("-e")
+        .arg(script)
+        .arg(hex)
+        .stderr(Stdio::inherit())
+        .output()
+        .expect("node should be installed");
+    assert!(output.status.success());
+    output.stdout
+}
+
+#[test]
+#[ignore = "needs wat2wasm and node"]
+fn wat_runs_like_program() {
+    let output = run_with_node(&compile(HELLO), b"");
+
+    let mut program = Program::with_io(
+        parse(HELLO.as_bytes()).unwrap(),
+        std::io::empty(),
+        Vec::new(),
+    );
+    program.run().unwrap();
+    let mut expected = Vec::new();
+    expected.write_all(program.output()).unwrap();
+    assert_eq!(output, expected);
+}